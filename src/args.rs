@@ -1,19 +1,114 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, Error as ClapError, ErrorKind, SubCommand};
+use nuget::NugetVersion;
 
-pub const PACK_CMD: &'static str = "pack";
-pub const CROSS_CMD: &'static str = "cross";
+pub const PACK_CMD: &str = "pack";
+pub const CROSS_CMD: &str = "cross";
 
-pub const CARGO_WORK_DIR_ARG: &'static str = "cargo-dir";
-pub const CARGO_BUILD_QUIET_ARG: &'static str = "cargo-build-quiet";
-pub const TARGETS_ARG: &'static str = "targets";
-pub const TEST_ARG: &'static str = "test";
-pub const RELEASE_ARG: &'static str = "release";
-pub const NUPKG_DIR_ARG: &'static str = "nupkg-dir";
+pub const CARGO_WORK_DIR_ARG: &str = "cargo-dir";
+pub const MANIFEST_PATH_ARG: &str = "manifest-path";
+pub const PACKAGE_ARG: &str = "package";
+pub const WORKSPACE_ARG: &str = "workspace";
+pub const CARGO_BUILD_QUIET_ARG: &str = "cargo-build-quiet";
+pub const TARGETS_ARG: &str = "targets";
+pub const TARGET_ARG: &str = "target";
+pub const TEST_ARG: &str = "test";
+pub const RELEASE_ARG: &str = "release";
+pub const NUPKG_DIR_ARG: &str = "nupkg-dir";
+pub const VERSION_ARG: &str = "version";
+pub const VERSION_STRATEGY_ARG: &str = "version-strategy";
+pub const VERSION_LABEL_ARG: &str = "version-label";
+pub const PROFILE_ARG: &str = "profile";
+pub const CARGO_ARGS_ARG: &str = "cargo-args";
+pub const TOOLCHAIN_ARG: &str = "toolchain";
+pub const JOBS_ARG: &str = "jobs";
+pub const FEATURES_ARG: &str = "features";
+pub const NO_DEFAULT_FEATURES_ARG: &str = "no-default-features";
+pub const ALL_FEATURES_ARG: &str = "all-features";
+pub const VERSION_FROM_GIT_ARG: &str = "version-from-git";
+pub const VERSION_OVERRIDE_ARG: &str = "version-override";
 
-pub fn target_path_arg(target: CrossTarget) -> String {
-    format!("{}-path", target.rid())
+pub const VERSION_STRATEGIES: &[&str] =
+    &["timestamp", "counter", "git", "ci", "manifest"];
+
+pub fn target_path_arg(rid: &Rid) -> String {
+    format!("{}-path", rid.rid())
+}
+
+/// Whether there's a `--{rid}-path` arg for the rid.
+///
+/// Only portable rids that can be built by a Rust target triple have one.
+pub fn has_target_path_arg(rid: &Rid) -> bool {
+    let arg = target_path_arg(rid);
+
+    TARGET_PATHS.iter().any(|path| path.long == arg)
+}
+
+/// Check that a target is a known Rust target triple.
+fn validate_triple(triple: String) -> Result<(), String> {
+    match Triple::from_triple(&triple) {
+        Some(_) => Ok(()),
+        None => Err(format!("'{}' is not a supported Rust target triple", triple)),
+    }
+}
+
+/// Check that a version is a valid NuGet version.
+fn validate_version(version: String) -> Result<(), String> {
+    NugetVersion::parse(&version).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Check that a number of jobs is a positive integer.
+fn validate_jobs(jobs: String) -> Result<(), String> {
+    match jobs.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(()),
+        _ => Err(format!("'{}' isn't a positive number of jobs", jobs)),
+    }
+}
+
+/// Check that a target is either a valid rid or Rust target triple.
+fn validate_target(target: String) -> Result<(), String> {
+    match Triple::from_triple(&target) {
+        Some(_) => Ok(()),
+        None => Rid::parse(&target).map(|_| ()).map_err(|e| e.to_string()),
+    }
+}
+
+/// Check that each target in `--targets` is a different rid.
+///
+/// A rid and its equivalent triple, like `win-x64` and `x86_64-pc-windows-msvc`, are the same
+/// target, so only one of them can be given.
+pub fn validate_targets<'a>(args: &'a ArgMatches<'a>) -> Result<(), ClapError> {
+    let mut rids = HashMap::new();
+
+    for target in args.values_of(TARGETS_ARG).into_iter().flatten() {
+        let rid = match Triple::from_triple(target) {
+            Some(triple) => Rid::from(triple.target()),
+            None => match Rid::parse(target) {
+                Ok(rid) => rid,
+                Err(_) => continue,
+            },
+        };
+
+        if let Some(other) = rids.insert(rid.rid(), target) {
+            let msg = if other == target {
+                format!("The target '{}' was given more than once", target)
+            } else {
+                format!(
+                    "The targets '{}' and '{}' are both the '{}' rid\nOnly pass one of them to `--{}`",
+                    other,
+                    target,
+                    rid.rid(),
+                    TARGETS_ARG
+                )
+            };
+
+            Err(ClapError::with_description(&msg, ErrorKind::ArgumentConflict))?;
+        }
+    }
+
+    Ok(())
 }
 
 struct PartialArg {
@@ -26,20 +121,31 @@ lazy_static! {
     static ref TARGET_PATHS: Vec<PartialArg> = {
         let archs = vec![
             Arch::x86,
-            Arch::x64
+            Arch::x64,
+            Arch::arm,
+            Arch::arm64,
+            Arch::s390x,
+            Arch::ppc64le,
+            Arch::loongarch64,
         ];
 
         archs.into_iter().flat_map(|arch| {
             vec![
                 CrossTarget::Windows(arch),
                 CrossTarget::MacOS(arch),
-                CrossTarget::Linux(arch),
+                CrossTarget::Linux(Libc::Gnu, arch),
+                CrossTarget::Linux(Libc::Musl, arch),
             ]
         })
-        .map(|target| PartialArg {
-            name: target_path_arg(target),
-            long: target_path_arg(target),
-            help: format!("a specific path to the output for the {} target", target.rid())
+        .filter(|target| Triple::new(*target).is_some())
+        .map(|target| {
+            let rid = Rid::from(target);
+
+            PartialArg {
+                name: target_path_arg(&rid),
+                long: target_path_arg(&rid),
+                help: format!("a specific path to the output for the {} target", rid.rid())
+            }
         })
         .collect()
     };
@@ -50,7 +156,26 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
         Arg::with_name(CARGO_WORK_DIR_ARG)
             .long(CARGO_WORK_DIR_ARG)
             .takes_value(true)
+            .conflicts_with(MANIFEST_PATH_ARG)
             .help("path to the Rust crate"),
+        Arg::with_name(MANIFEST_PATH_ARG)
+            .long(MANIFEST_PATH_ARG)
+            .takes_value(true)
+            .help("path to the Cargo.toml of the Rust crate or workspace"),
+        Arg::with_name(PACKAGE_ARG)
+            .short("p")
+            .long(PACKAGE_ARG)
+            .takes_value(true)
+            .help("package in the workspace to pack"),
+        Arg::with_name(WORKSPACE_ARG)
+            .long(WORKSPACE_ARG)
+            .conflicts_with(PACKAGE_ARG)
+            .help("pack every dylib in the workspace"),
+        Arg::with_name(TARGET_ARG)
+            .long(TARGET_ARG)
+            .takes_value(true)
+            .validator(validate_triple)
+            .help("Rust target triple to build instead of the rustc host"),
         Arg::with_name(CARGO_BUILD_QUIET_ARG)
             .short("q")
             .long(CARGO_BUILD_QUIET_ARG)
@@ -63,10 +188,50 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
             .short("r")
             .long(RELEASE_ARG)
             .help("run an optimised build"),
+        Arg::with_name(PROFILE_ARG)
+            .long(PROFILE_ARG)
+            .takes_value(true)
+            .conflicts_with(RELEASE_ARG)
+            .help("build with a custom cargo profile"),
+        Arg::with_name(TOOLCHAIN_ARG)
+            .long(TOOLCHAIN_ARG)
+            .takes_value(true)
+            .help("the rustup toolchain to build with, like `nightly`"),
+        Arg::with_name(FEATURES_ARG)
+            .long(FEATURES_ARG)
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .help("crate features to enable"),
+        Arg::with_name(NO_DEFAULT_FEATURES_ARG)
+            .long(NO_DEFAULT_FEATURES_ARG)
+            .help("don't enable the default crate features"),
+        Arg::with_name(ALL_FEATURES_ARG)
+            .long(ALL_FEATURES_ARG)
+            .help("enable all crate features"),
         Arg::with_name(NUPKG_DIR_ARG)
             .long(NUPKG_DIR_ARG)
             .takes_value(true)
             .help("path to save the nupkg"),
+        Arg::with_name(VERSION_ARG)
+            .long(VERSION_ARG)
+            .takes_value(true)
+            .validator(validate_version)
+            .help("version to give the package, instead of generating one"),
+        Arg::with_name(VERSION_STRATEGY_ARG)
+            .long(VERSION_STRATEGY_ARG)
+            .takes_value(true)
+            .possible_values(VERSION_STRATEGIES)
+            .help("how to generate the package version from the crate version"),
+        Arg::with_name(VERSION_LABEL_ARG)
+            .long(VERSION_LABEL_ARG)
+            .takes_value(true)
+            .help("pre-release label to add to generated versions, like `dev`"),
+        Arg::with_name(CARGO_ARGS_ARG)
+            .multiple(true)
+            .last(true)
+            .allow_hyphen_values(true)
+            .help("args to pass to every cargo command, like `-- --locked`"),
     ];
 
     let path_args = TARGET_PATHS.iter().map(|arg| {
@@ -80,13 +245,29 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
         Arg::with_name(CARGO_WORK_DIR_ARG)
             .long(CARGO_WORK_DIR_ARG)
             .takes_value(true)
+            .conflicts_with(MANIFEST_PATH_ARG)
             .help("path to the Rust crate"),
+        Arg::with_name(MANIFEST_PATH_ARG)
+            .long(MANIFEST_PATH_ARG)
+            .takes_value(true)
+            .help("path to the Cargo.toml of the Rust crate or workspace"),
+        Arg::with_name(PACKAGE_ARG)
+            .short("p")
+            .long(PACKAGE_ARG)
+            .takes_value(true)
+            .help("package in the workspace to pack"),
         Arg::with_name(TARGETS_ARG)
             .long(TARGETS_ARG)
             .takes_value(true)
-            .required(true)
             .multiple(true)
-            .help("set of dotnet rids to include"),
+            .validator(validate_target)
+            .help("set of dotnet rids or Rust target triples to include, instead of the manifest targets"),
+        Arg::with_name(JOBS_ARG)
+            .short("j")
+            .long(JOBS_ARG)
+            .takes_value(true)
+            .validator(validate_jobs)
+            .help("number of targets to build at once, instead of all of them"),
         Arg::with_name(CARGO_BUILD_QUIET_ARG)
             .short("q")
             .long(CARGO_BUILD_QUIET_ARG)
@@ -99,10 +280,58 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
             .short("r")
             .long(RELEASE_ARG)
             .help("run an optimised build"),
+        Arg::with_name(PROFILE_ARG)
+            .long(PROFILE_ARG)
+            .takes_value(true)
+            .conflicts_with(RELEASE_ARG)
+            .help("build with a custom cargo profile"),
+        Arg::with_name(TOOLCHAIN_ARG)
+            .long(TOOLCHAIN_ARG)
+            .takes_value(true)
+            .help("the rustup toolchain to build with, like `nightly`"),
+        Arg::with_name(FEATURES_ARG)
+            .long(FEATURES_ARG)
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .help("crate features to enable"),
+        Arg::with_name(NO_DEFAULT_FEATURES_ARG)
+            .long(NO_DEFAULT_FEATURES_ARG)
+            .help("don't enable the default crate features"),
+        Arg::with_name(ALL_FEATURES_ARG)
+            .long(ALL_FEATURES_ARG)
+            .help("enable all crate features"),
         Arg::with_name(NUPKG_DIR_ARG)
             .long(NUPKG_DIR_ARG)
             .takes_value(true)
             .help("path to save the nupkg"),
+        Arg::with_name(VERSION_ARG)
+            .long(VERSION_ARG)
+            .takes_value(true)
+            .validator(validate_version)
+            .help("version to give the package, instead of generating one"),
+        Arg::with_name(VERSION_STRATEGY_ARG)
+            .long(VERSION_STRATEGY_ARG)
+            .takes_value(true)
+            .possible_values(VERSION_STRATEGIES)
+            .help("how to generate the package version from the crate version"),
+        Arg::with_name(VERSION_LABEL_ARG)
+            .long(VERSION_LABEL_ARG)
+            .takes_value(true)
+            .help("pre-release label to add to generated versions, like `dev`"),
+        Arg::with_name(VERSION_FROM_GIT_ARG)
+            .long(VERSION_FROM_GIT_ARG)
+            .conflicts_with(VERSION_ARG)
+            .help("take the package version from a `v*` git tag on the current commit"),
+        Arg::with_name(VERSION_OVERRIDE_ARG)
+            .long(VERSION_OVERRIDE_ARG)
+            .requires(VERSION_FROM_GIT_ARG)
+            .help("use the git tag version even if it doesn't match the crate version"),
+        Arg::with_name(CARGO_ARGS_ARG)
+            .multiple(true)
+            .last(true)
+            .allow_hyphen_values(true)
+            .help("args to pass to every cargo command, like `-- --locked`"),
     ];
 
     cross_args.extend(path_args);
@@ -127,25 +356,36 @@ pub enum Action {
     Test,
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Profile {
     Debug,
     Release,
+    /// A custom profile from a `[profile.{name}]` table.
+    Custom(String),
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+impl Profile {
+    /// Get a profile from its name, like `release`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "dev" | "debug" => Profile::Debug,
+            "release" => Profile::Release,
+            name => Profile::Custom(name.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Target {
-    Local,
     Unknown,
-    Cross(CrossTarget),
+    Cross(Rid),
 }
 
 impl Target {
     pub fn cross(&self) -> Option<CrossTarget> {
         match *self {
-            Target::Local => CrossTarget::local(),
             Target::Unknown => None,
-            Target::Cross(target) => Some(target),
+            Target::Cross(ref rid) => Some(rid.target()),
         }
     }
 
@@ -153,17 +393,20 @@ impl Target {
         self.cross().is_none()
     }
 
-    pub fn rid(&self) -> Cow<'static, str> {
-        match self.cross() {
-            Some(target) => target.rid(),
-            _ => "any".into(),
+    /// Get the rid for the target.
+    ///
+    /// This will return `None` if the target isn't known.
+    pub fn rid(&self) -> Option<Cow<'static, str>> {
+        match *self {
+            Target::Unknown => None,
+            Target::Cross(ref rid) => Some(rid.rid()),
         }
     }
 
     pub fn from_rid(rid: &str) -> Self {
-        match CrossTarget::from_rid(rid) {
-            Some(target) => Target::Cross(target),
-            None => Target::Unknown,
+        match Rid::parse(rid) {
+            Ok(rid) => Target::Cross(rid),
+            Err(_) => Target::Unknown,
         }
     }
 }
@@ -171,37 +414,257 @@ impl Target {
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum CrossTarget {
     Windows(Arch),
-    Linux(Arch),
+    Linux(Libc, Arch),
     MacOS(Arch),
 }
 
 impl CrossTarget {
-    pub fn local() -> Option<Self> {
-        local_target()
+    /// Get the portable rid for the target, like `win-x64`.
+    pub fn rid(&self) -> Cow<'static, str> {
+        rid(self.os(), self.arch().rid())
+    }
+
+    /// Get the portable rid operating system for the target, like `win`.
+    pub fn os(&self) -> &'static str {
+        match *self {
+            CrossTarget::Windows(_) => "win",
+            CrossTarget::MacOS(_) => "osx",
+            CrossTarget::Linux(Libc::Gnu, _) => "linux",
+            CrossTarget::Linux(Libc::Musl, _) => "linux-musl",
+        }
+    }
+
+    pub fn arch(&self) -> Arch {
+        match *self {
+            CrossTarget::Windows(arch) |
+            CrossTarget::MacOS(arch) |
+            CrossTarget::Linux(_, arch) => arch,
+        }
+    }
+
+    pub fn from_rid(rid: &str) -> Option<Self> {
+        Rid::parse(rid).ok().map(|rid| rid.target())
+    }
+}
+
+/// A dotnet runtime identifier.
+///
+/// Rids have the form `{os}[.{version}]-{arch}[-{qualifier}]`, like `win-x64`,
+/// `win10-x64`, `osx.10.12-x64`, `ubuntu.18.04-x64` or `win7-x86-aot`.
+/// Every rid maps to a platform target that can be built.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Rid {
+    target: CrossTarget,
+    os: Cow<'static, str>,
+    version: Option<String>,
+    qualifier: Option<String>,
+}
+
+impl From<CrossTarget> for Rid {
+    fn from(target: CrossTarget) -> Self {
+        Rid {
+            target,
+            os: target.os().into(),
+            version: None,
+            qualifier: None,
+        }
+    }
+}
+
+impl Rid {
+    /// Parse a rid.
+    ///
+    /// Rids with an unknown operating system, architecture or qualifier are rejected.
+    pub fn parse(rid: &str) -> Result<Self, RidError> {
+        let mut parts = rid.split("-").peekable();
+
+        let (os, version) = parts
+            .next()
+            .map(split_os_version)
+            .unwrap_or(("", None));
+
+        // Linux rids may include a libc, like `linux-musl-x64`
+        let libc = match (os, &version, parts.peek().and_then(|libc| Libc::from_rid(libc))) {
+            ("linux", &None, Some(libc)) => {
+                parts.next();
+                libc
+            }
+            _ => Libc::Gnu,
+        };
+
+        let arch = match parts.next() {
+            Some(arch) => Arch::from_rid(arch).ok_or_else(|| RidError::UnknownArch {
+                rid: rid.into(),
+                arch: arch.into(),
+            })?,
+            None => Err(RidError::Malformed { rid: rid.into() })?,
+        };
+
+        let qualifier = match parts.next() {
+            Some(qualifier) if is_qualifier(qualifier) => Some(qualifier.to_owned()),
+            Some(qualifier) => Err(RidError::UnknownQualifier {
+                rid: rid.into(),
+                qualifier: qualifier.into(),
+            })?,
+            None => None,
+        };
+
+        if parts.next().is_some() {
+            Err(RidError::Malformed { rid: rid.into() })?;
+        }
+
+        let target = os_target(os, libc, arch).ok_or_else(|| RidError::UnknownOs {
+            rid: rid.into(),
+            os: os.into(),
+        })?;
+
+        if let Some(ref version) = version {
+            if !is_version(os, version) {
+                Err(RidError::BadVersion {
+                    rid: rid.into(),
+                    version: version.clone(),
+                })?;
+            }
+        }
+
+        let os = match libc {
+            Libc::Musl => target.os().into(),
+            Libc::Gnu => Cow::Owned(os.to_owned()),
+        };
+
+        let parsed = Rid {
+            target,
+            os,
+            version,
+            qualifier,
+        };
+
+        // Only accept rids in their canonical form, so `win.10-x64` isn't `win10-x64`
+        match parsed.rid() == rid {
+            true => Ok(parsed),
+            false => Err(RidError::Malformed { rid: rid.into() }),
+        }
+    }
+
+    /// Get the platform target the rid is built for.
+    pub fn target(&self) -> CrossTarget {
+        self.target
+    }
+
+    /// Whether the rid is a portable rid without a version or qualifier, like `linux-x64`.
+    pub fn is_portable(&self) -> bool {
+        self.version.is_none() && self.qualifier.is_none() && self.os == self.target.os()
     }
 
     pub fn rid(&self) -> Cow<'static, str> {
+        let mut rid = self.os.to_string();
+
+        if let Some(ref version) = self.version {
+            // Windows versions aren't dot separated, like `win10`
+            if self.os != "win" {
+                rid.push('.');
+            }
+
+            rid.push_str(version);
+        }
+
+        rid.push('-');
+        rid.push_str(self.target.arch().rid());
+
+        if let Some(ref qualifier) = self.qualifier {
+            rid.push('-');
+            rid.push_str(qualifier);
+        }
+
+        rid.into()
+    }
+}
+
+/// Split an rid operating system from its version, like `osx.10.12` or `win10`.
+fn split_os_version(os: &str) -> (&str, Option<String>) {
+    match os.find('.') {
+        Some(idx) => (&os[..idx], Some(os[idx + 1..].to_owned())),
+        None if os.starts_with("win") && os.len() > 3 => ("win", Some(os[3..].to_owned())),
+        None => (os, None),
+    }
+}
+
+/// Get the platform target for a known rid operating system.
+fn os_target(os: &str, libc: Libc, arch: Arch) -> Option<CrossTarget> {
+    match os {
+        "win" => Some(CrossTarget::Windows(arch)),
+        "osx" => Some(CrossTarget::MacOS(arch)),
+        "linux" => Some(CrossTarget::Linux(libc, arch)),
+        "alpine" => Some(CrossTarget::Linux(Libc::Musl, arch)),
+        "centos" | "debian" | "fedora" | "linuxmint" | "ol" | "opensuse" | "rhel" | "sles" |
+        "tizen" | "ubuntu" => Some(CrossTarget::Linux(Libc::Gnu, arch)),
+        _ => None,
+    }
+}
+
+fn is_version(os: &str, version: &str) -> bool {
+    let is_num = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+
+    match os {
+        "win" => is_num(version),
+        _ => version.split('.').all(is_num),
+    }
+}
+
+fn is_qualifier(qualifier: &str) -> bool {
+    matches!(qualifier, "aot" | "corert")
+}
+
+quick_error!{
+    /// An error encountered while parsing a rid.
+    #[derive(Debug)]
+    pub enum RidError {
+        /// The rid doesn't match the `{os}[.{version}]-{arch}[-{qualifier}]` grammar.
+        Malformed { rid: String } {
+            display("'{}' is not a valid rid\nRids look like `win-x64` or `ubuntu.18.04-x64`", rid)
+        }
+        /// The rid operating system isn't known.
+        UnknownOs { rid: String, os: String } {
+            display("'{}' is not a valid rid\nThe operating system '{}' isn't known", rid, os)
+        }
+        /// The rid architecture isn't known.
+        UnknownArch { rid: String, arch: String } {
+            display("'{}' is not a valid rid\nThe architecture '{}' isn't known", rid, arch)
+        }
+        /// The rid version isn't a dot separated number.
+        BadVersion { rid: String, version: String } {
+            display("'{}' is not a valid rid\nThe version '{}' isn't a number", rid, version)
+        }
+        /// The rid qualifier isn't known.
+        UnknownQualifier { rid: String, qualifier: String } {
+            display("'{}' is not a valid rid\nThe qualifier '{}' isn't known", rid, qualifier)
+        }
+    }
+}
+
+/// The C standard library a Linux target links against.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+impl Libc {
+    /// Get the rid part for the libc.
+    ///
+    /// `glibc` is the default on Linux so it doesn't have an rid part.
+    pub fn rid(&self) -> Option<&'static str> {
         match *self {
-            CrossTarget::Windows(arch) => rid("win", arch.rid()),
-            CrossTarget::MacOS(arch) => rid("osx", arch.rid()),
-            CrossTarget::Linux(arch) => rid("linux", arch.rid()),
+            Libc::Gnu => None,
+            Libc::Musl => Some("musl"),
         }
     }
 
     pub fn from_rid(rid: &str) -> Option<Self> {
-        let mut parts = rid.split("-");
-
-        let platform = parts.next();
-        let arch = parts.next().and_then(Arch::from_rid);
-
-        platform
-            .and_then(|platform| arch.map(|arch| (platform, arch)))
-            .and_then(|(platform, arch)| match platform {
-                "win" => Some(CrossTarget::Windows(arch)),
-                "osx" => Some(CrossTarget::MacOS(arch)),
-                "linux" => Some(CrossTarget::Linux(arch)),
-                _ => None,
-            })
+        match rid {
+            "musl" => Some(Libc::Musl),
+            _ => None,
+        }
     }
 }
 
@@ -210,17 +673,23 @@ impl CrossTarget {
 pub enum Arch {
     x64,
     x86,
+    arm,
+    arm64,
+    s390x,
+    ppc64le,
+    loongarch64,
 }
 
 impl Arch {
-    pub fn local() -> Option<Self> {
-        local_arch()
-    }
-
     pub fn rid(&self) -> &'static str {
         match *self {
             Arch::x86 => "x86",
             Arch::x64 => "x64",
+            Arch::arm => "arm",
+            Arch::arm64 => "arm64",
+            Arch::s390x => "s390x",
+            Arch::ppc64le => "ppc64le",
+            Arch::loongarch64 => "loongarch64",
         }
     }
 
@@ -228,51 +697,249 @@ impl Arch {
         match rid {
             "x86" => Some(Arch::x86),
             "x64" => Some(Arch::x64),
+            "arm" => Some(Arch::arm),
+            "arm64" => Some(Arch::arm64),
+            "s390x" => Some(Arch::s390x),
+            "ppc64le" => Some(Arch::ppc64le),
+            "loongarch64" => Some(Arch::loongarch64),
             _ => None,
         }
     }
-}
 
-fn rid(target: &'static str, arch: &'static str) -> Cow<'static, str> {
-    format!("{}-{}", target, arch).into()
-}
+    pub fn triple(&self) -> &'static str {
+        match *self {
+            Arch::x86 => "i686",
+            Arch::x64 => "x86_64",
+            Arch::arm => "armv7",
+            Arch::arm64 => "aarch64",
+            Arch::s390x => "s390x",
+            Arch::ppc64le => "powerpc64le",
+            Arch::loongarch64 => "loongarch64",
+        }
+    }
 
-#[cfg(target_arch = "x86")]
-fn local_arch() -> Option<Arch> {
-    Some(Arch::x86)
-}
-#[cfg(target_arch = "x86_64")]
-fn local_arch() -> Option<Arch> {
-    Some(Arch::x64)
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        match triple {
+            "i686" => Some(Arch::x86),
+            "x86_64" => Some(Arch::x64),
+            "armv7" | "thumbv7a" => Some(Arch::arm),
+            "aarch64" => Some(Arch::arm64),
+            "s390x" => Some(Arch::s390x),
+            "powerpc64le" => Some(Arch::ppc64le),
+            "loongarch64" => Some(Arch::loongarch64),
+            _ => None,
+        }
+    }
 }
 
-#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
-fn local_arch() -> Option<Arch> {
-    None
+/// A Rust target triple for a platform target.
+///
+/// Some targets can be built by more than one triple, like `x86_64-pc-windows-msvc`
+/// and `x86_64-pc-windows-gnu` for `win-x64`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Triple {
+    target: CrossTarget,
+    env: Option<Env>,
 }
 
-#[cfg(windows)]
-fn local_target() -> Option<CrossTarget> {
-    local_arch().map(|arch| CrossTarget::Windows(arch))
+impl Triple {
+    /// Get the default triple for a target.
+    ///
+    /// This will return `None` if there's no Rust target triple for the target.
+    pub fn new(target: CrossTarget) -> Option<Self> {
+        match target {
+            CrossTarget::Windows(_) => Triple::with_env(target, Env::Msvc),
+            CrossTarget::Linux(Libc::Gnu, _) => Triple::with_env(target, Env::Gnu),
+            CrossTarget::Linux(Libc::Musl, _) => Triple::with_env(target, Env::Musl),
+            CrossTarget::MacOS(Arch::x86) |
+            CrossTarget::MacOS(Arch::x64) |
+            CrossTarget::MacOS(Arch::arm64) => Some(Triple {
+                target,
+                env: None,
+            }),
+            CrossTarget::MacOS(_) => None,
+        }
+    }
+
+    /// Get the triple for a target built with a specific environment.
+    ///
+    /// This will return `None` if the target can't be built with the environment.
+    pub fn with_env(target: CrossTarget, env: Env) -> Option<Self> {
+        match (target, env) {
+            (CrossTarget::Windows(Arch::x86), _) |
+            (CrossTarget::Windows(Arch::x64), _) |
+            (CrossTarget::Windows(Arch::arm), Env::Msvc) |
+            (CrossTarget::Windows(Arch::arm64), Env::Msvc) |
+            (CrossTarget::Linux(Libc::Gnu, _), Env::Gnu) |
+            (CrossTarget::Linux(Libc::Musl, _), Env::Musl) => Some(Triple {
+                target,
+                env: Some(env),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn target(&self) -> CrossTarget {
+        self.target
+    }
+
+    pub fn env(&self) -> Option<Env> {
+        self.env
+    }
+
+    pub fn triple(&self) -> Cow<'static, str> {
+        let arch = match self.target {
+            CrossTarget::Windows(Arch::arm) => "thumbv7a",
+            target => target.arch().triple(),
+        };
+
+        let (vendor, os) = match self.target {
+            CrossTarget::Windows(_) => ("pc", "windows"),
+            CrossTarget::MacOS(_) => ("apple", "darwin"),
+            CrossTarget::Linux(..) => ("unknown", "linux"),
+        };
+
+        // 32bit ARM on Linux targets the hard-float ABI
+        let env = match (self.target, self.env) {
+            (CrossTarget::Linux(_, Arch::arm), Some(Env::Gnu)) => Some("gnueabihf"),
+            (CrossTarget::Linux(_, Arch::arm), Some(Env::Musl)) => Some("musleabihf"),
+            (_, env) => env.map(|env| env.triple()),
+        };
+
+        match env {
+            Some(env) => format!("{}-{}-{}-{}", arch, vendor, os, env).into(),
+            None => format!("{}-{}-{}", arch, vendor, os).into(),
+        }
+    }
+
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        let mut parts = triple.split("-");
+
+        let arch = parts.next().and_then(Arch::from_triple)?;
+
+        let parsed = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("pc"), Some("windows"), Some(env), None) => Env::from_triple(env)
+                .and_then(|env| Triple::with_env(CrossTarget::Windows(arch), env)),
+            (Some("unknown"), Some("linux"), Some(env), None) => Env::from_triple(env)
+                .and_then(|env| {
+                    let libc = match env {
+                        Env::Musl => Libc::Musl,
+                        _ => Libc::Gnu,
+                    };
+
+                    Triple::with_env(CrossTarget::Linux(libc, arch), env)
+                }),
+            (Some("apple"), Some("darwin"), None, None) => Triple::new(CrossTarget::MacOS(arch)),
+            _ => None,
+        };
+
+        // Only accept the exact triple for the target, so `armv7-pc-windows-msvc` isn't `win-arm`
+        parsed.and_then(|parsed| match parsed.triple() == triple {
+            true => Some(parsed),
+            false => None,
+        })
+    }
 }
-#[cfg(target_os = "macos")]
-fn local_target() -> Option<CrossTarget> {
-    local_arch().map(|arch| CrossTarget::MacOS(arch))
+
+/// The environment a Rust target triple is built for.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Env {
+    Gnu,
+    Musl,
+    Msvc,
 }
-#[cfg(target_os = "linux")]
-fn local_target() -> Option<CrossTarget> {
-    local_arch().map(|arch| CrossTarget::Linux(arch))
+
+impl Env {
+    pub fn triple(&self) -> &'static str {
+        match *self {
+            Env::Gnu => "gnu",
+            Env::Musl => "musl",
+            Env::Msvc => "msvc",
+        }
+    }
+
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        match triple {
+            "gnu" => Some(Env::Gnu),
+            "gnueabihf" => Some(Env::Gnu),
+            "musl" | "musleabihf" => Some(Env::Musl),
+            "msvc" => Some(Env::Msvc),
+            _ => None,
+        }
+    }
 }
 
-#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
-fn local_target() -> Option<CrossTarget> {
-    None
+fn rid(target: &'static str, arch: &'static str) -> Cow<'static, str> {
+    format!("{}-{}", target, arch).into()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn profile_names() {
+        let cases = vec![
+            ("dev", Profile::Debug),
+            ("debug", Profile::Debug),
+            ("release", Profile::Release),
+            ("ci", Profile::Custom("ci".into())),
+        ];
+
+        for (name, profile) in cases {
+            assert_eq!(profile, Profile::from_name(name));
+        }
+    }
+
+    #[test]
+    fn cargo_args_after_separator() {
+        let args = app().get_matches_from(vec![
+            "cargo-nuget",
+            "pack",
+            "--features",
+            "simd",
+            "--",
+            "--locked",
+            "-Z",
+            "unstable-options",
+        ]);
+
+        let args = args.subcommand_matches(PACK_CMD).unwrap();
+
+        let cargo_args: Vec<_> = args.values_of(CARGO_ARGS_ARG).unwrap().collect();
+
+        assert_eq!(vec!["--locked", "-Z", "unstable-options"], cargo_args);
+        assert_eq!(vec!["simd"], args.values_of(FEATURES_ARG).unwrap().collect::<Vec<_>>());
+    }
+
+    fn cross_targets(targets: &[&str]) -> Result<(), ClapError> {
+        let args = app().get_matches_from(
+            vec!["cargo-nuget", "cross", "--targets"].into_iter().chain(targets.iter().cloned()),
+        );
+
+        validate_targets(args.subcommand_matches(CROSS_CMD).unwrap())
+    }
+
+    #[test]
+    fn distinct_targets() {
+        assert!(cross_targets(&["win-x64", "win10-x64", "x86_64-unknown-linux-gnu"]).is_ok());
+    }
+
+    #[test]
+    fn duplicate_targets() {
+        let cases = vec![
+            vec!["win-x64", "linux-x64", "win-x64"],
+            vec!["win-x64", "x86_64-pc-windows-msvc"],
+            vec!["x86_64-unknown-linux-gnu", "linux-x64"],
+        ];
+
+        for targets in cases {
+            let err = cross_targets(&targets).unwrap_err();
+
+            assert_eq!(ErrorKind::ArgumentConflict, err.kind);
+        }
+    }
+
     #[test]
     fn unknown_rid() {
         let target = Target::from_rid("mcnuggets");
@@ -286,8 +953,8 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::Windows(Arch::x86)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::Windows(Arch::x86).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
     }
 
     #[test]
@@ -296,8 +963,8 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::Windows(Arch::x64)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::Windows(Arch::x64).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
     }
 
     #[test]
@@ -306,8 +973,8 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::MacOS(Arch::x86)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::MacOS(Arch::x86).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
     }
 
     #[test]
@@ -316,8 +983,8 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::MacOS(Arch::x64)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::MacOS(Arch::x64).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
     }
 
     #[test]
@@ -326,8 +993,8 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::Linux(Arch::x86)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::Linux(Libc::Gnu, Arch::x86).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
     }
 
     #[test]
@@ -336,7 +1003,170 @@ mod tests {
 
         let target = Target::from_rid(rid);
 
-        assert_eq!(Target::Cross(CrossTarget::Linux(Arch::x64)), target);
-        assert_eq!(rid, target.rid());
+        assert_eq!(Target::Cross(CrossTarget::Linux(Libc::Gnu, Arch::x64).into()), target);
+        assert_eq!(Some(rid.into()), target.rid());
+    }
+
+    #[test]
+    fn unknown_triple() {
+        assert_eq!(None, Triple::from_triple("mcnuggets"));
+        assert_eq!(None, Triple::from_triple("x86_64-apple-darwin-gnu"));
+        assert_eq!(None, Triple::from_triple("x86_64-unknown-linux-msvc"));
+        assert_eq!(None, Triple::from_triple("armv7-unknown-linux-gnu"));
+        assert_eq!(None, Triple::from_triple("s390x-apple-darwin"));
+    }
+
+    #[test]
+    fn arm_rids() {
+        let rids = vec![
+            ("win-arm64", CrossTarget::Windows(Arch::arm64)),
+            ("osx-arm64", CrossTarget::MacOS(Arch::arm64)),
+            ("linux-arm", CrossTarget::Linux(Libc::Gnu, Arch::arm)),
+            ("linux-arm64", CrossTarget::Linux(Libc::Gnu, Arch::arm64)),
+            ("linux-s390x", CrossTarget::Linux(Libc::Gnu, Arch::s390x)),
+            ("linux-ppc64le", CrossTarget::Linux(Libc::Gnu, Arch::ppc64le)),
+            ("linux-loongarch64", CrossTarget::Linux(Libc::Gnu, Arch::loongarch64)),
+        ];
+
+        for (rid, expected) in rids {
+            let target = Target::from_rid(rid);
+
+            assert_eq!(Target::Cross(expected.into()), target);
+            assert_eq!(Some(rid.into()), target.rid());
+        }
+    }
+
+    #[test]
+    fn target_path_args_have_triples() {
+        let args: Vec<_> = TARGET_PATHS.iter().map(|arg| arg.long.as_ref()).collect();
+
+        assert!(args.contains(&"osx-arm64-path"));
+        assert!(args.contains(&"linux-arm-path"));
+        assert!(args.contains(&"linux-musl-x64-path"));
+        assert!(!args.contains(&"osx-s390x-path"));
+    }
+
+    #[test]
+    fn has_target_path_args() {
+        let rid = |rid| Rid::parse(rid).unwrap();
+
+        assert!(has_target_path_arg(&rid("win-x64")));
+        assert!(!has_target_path_arg(&rid("win10-x64")));
+        assert!(!has_target_path_arg(&rid("osx-s390x")));
+    }
+
+    #[test]
+    fn linux_musl_rids() {
+        let rids = vec![
+            ("linux-musl-x64", CrossTarget::Linux(Libc::Musl, Arch::x64)),
+            ("linux-musl-arm64", CrossTarget::Linux(Libc::Musl, Arch::arm64)),
+            ("linux-musl-arm", CrossTarget::Linux(Libc::Musl, Arch::arm)),
+        ];
+
+        for (rid, expected) in rids {
+            let target = Target::from_rid(rid);
+
+            assert_eq!(Target::Cross(expected.into()), target);
+            assert_eq!(Some(rid.into()), target.rid());
+        }
+
+        assert_eq!(Target::Unknown, Target::from_rid("win-musl-x64"));
+    }
+
+    #[test]
+    fn versioned_rids() {
+        let rids = vec![
+            ("win10-x64", CrossTarget::Windows(Arch::x64)),
+            ("win7-x86-aot", CrossTarget::Windows(Arch::x86)),
+            ("osx.10.12-x64", CrossTarget::MacOS(Arch::x64)),
+            ("ubuntu.18.04-x64", CrossTarget::Linux(Libc::Gnu, Arch::x64)),
+            ("rhel.8-arm64", CrossTarget::Linux(Libc::Gnu, Arch::arm64)),
+            ("alpine.3.9-x64", CrossTarget::Linux(Libc::Musl, Arch::x64)),
+        ];
+
+        for (rid, expected) in rids {
+            let parsed = Rid::parse(rid).unwrap();
+
+            assert_eq!(expected, parsed.target());
+            assert_eq!(rid, parsed.rid());
+            assert!(!parsed.is_portable());
+        }
+
+        assert!(Rid::parse("linux-musl-x64").unwrap().is_portable());
+    }
+
+    #[test]
+    fn invalid_rids() {
+        macro_rules! assert_invalid {
+            ($rid:expr, $err:pat) => ({
+                match Rid::parse($rid) {
+                    Err($err) => (),
+                    r => panic!("{:?}", r)
+                }
+            })
+        }
+
+        assert_invalid!("linux-x64-garbage", RidError::UnknownQualifier { .. });
+        assert_invalid!("linux-x64-aot-corert", RidError::Malformed { .. });
+        assert_invalid!("linux", RidError::Malformed { .. });
+        assert_invalid!("win.10-x64", RidError::Malformed { .. });
+        assert_invalid!("freebsd-x64", RidError::UnknownOs { .. });
+        assert_invalid!("ubuntu.18.04-mips", RidError::UnknownArch { .. });
+        assert_invalid!("ubuntu.bionic-x64", RidError::BadVersion { .. });
+        assert_invalid!("winxp-x86", RidError::BadVersion { .. });
+    }
+
+    #[test]
+    fn linux_musl_triples() {
+        let triples = vec![
+            (CrossTarget::Linux(Libc::Musl, Arch::x64), "x86_64-unknown-linux-musl"),
+            (CrossTarget::Linux(Libc::Musl, Arch::arm64), "aarch64-unknown-linux-musl"),
+            (CrossTarget::Linux(Libc::Musl, Arch::arm), "armv7-unknown-linux-musleabihf"),
+        ];
+
+        for (target, expected) in triples {
+            let triple = Triple::new(target).unwrap();
+
+            assert_eq!(expected, triple.triple());
+            assert_eq!(Some(triple), Triple::from_triple(expected));
+        }
+
+        assert_eq!(None, Triple::with_env(CrossTarget::Linux(Libc::Gnu, Arch::x64), Env::Musl));
+    }
+
+    #[test]
+    fn default_triples() {
+        let triples = vec![
+            (CrossTarget::Windows(Arch::x86), "i686-pc-windows-msvc"),
+            (CrossTarget::Windows(Arch::x64), "x86_64-pc-windows-msvc"),
+            (CrossTarget::MacOS(Arch::x86), "i686-apple-darwin"),
+            (CrossTarget::MacOS(Arch::x64), "x86_64-apple-darwin"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::x86), "i686-unknown-linux-gnu"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::x64), "x86_64-unknown-linux-gnu"),
+            (CrossTarget::Windows(Arch::arm), "thumbv7a-pc-windows-msvc"),
+            (CrossTarget::Windows(Arch::arm64), "aarch64-pc-windows-msvc"),
+            (CrossTarget::MacOS(Arch::arm64), "aarch64-apple-darwin"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::arm), "armv7-unknown-linux-gnueabihf"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::arm64), "aarch64-unknown-linux-gnu"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::s390x), "s390x-unknown-linux-gnu"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::ppc64le), "powerpc64le-unknown-linux-gnu"),
+            (CrossTarget::Linux(Libc::Gnu, Arch::loongarch64), "loongarch64-unknown-linux-gnu"),
+        ];
+
+        for (target, expected) in triples {
+            let triple = Triple::new(target).unwrap();
+
+            assert_eq!(expected, triple.triple());
+            assert_eq!(Some(triple), Triple::from_triple(expected));
+        }
+    }
+
+    #[test]
+    fn windows_gnu_triple() {
+        let triple = Triple::from_triple("x86_64-pc-windows-gnu").unwrap();
+
+        assert_eq!(CrossTarget::Windows(Arch::x64), triple.target());
+        assert_eq!(Some(Env::Gnu), triple.env());
+        assert_eq!("win-x64", triple.target().rid());
     }
 }
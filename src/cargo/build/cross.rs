@@ -1,67 +1,278 @@
 //! Run a `cargo` command that builds some output.
 
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
 
-use super::{CargoBuildError, CargoBuildOutput};
-use args::{Action, CrossTarget, Profile, Target};
+use super::{actions, cargo_commands, output_path, rustc, CargoBuildError, CargoBuildOutput,
+            CargoCommand, CargoEnv, CargoFeatures};
+use args::{Action, Profile, Rid, Target, Triple};
 
 /// Args for running a `cargo` command for the native package.
 #[derive(Debug, Clone, PartialEq)]
 pub struct CargoCrossBuildArgs<'a> {
     pub work_dir: Cow<'a, Path>,
+    /// The target directory of the crate.
+    ///
+    /// Targets are built in it as normal, except when more than one target builds the
+    /// same triple, like `win-x64` and `win10-x64`. Those targets are each built in their
+    /// own directory under `cargo-nuget/{rid}`, so their output doesn't overwrite each other.
+    pub target_dir: Cow<'a, Path>,
+    /// Additional args to pass to cargo for every target.
+    pub cargo_args: Vec<Cow<'a, str>>,
     pub quiet: bool,
-    pub targets: HashMap<CrossTarget, CargoCrossTarget<'a>>,
+    /// The number of targets to build at once, or all of them if there's no limit.
+    pub jobs: Option<usize>,
+    pub targets: HashMap<Rid, CargoCrossTarget<'a>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CargoCrossTarget<'a> {
     Build {
+        triple: Triple,
         action: Action,
         profile: Profile,
         output_name: Cow<'a, str>,
+        features: CargoFeatures<'a>,
+        /// The rustup toolchain to build with, like `nightly`.
+        toolchain: Option<Cow<'a, str>>,
+        /// Environment variables to set, like a linker to use.
+        env: CargoEnv<'a>,
+        /// Additional flags to pass to `rustc`.
+        rustflags: Vec<Cow<'a, str>>,
+        /// A command to run instead of `cargo build`, or empty to run cargo.
+        command: Vec<Cow<'a, str>>,
     },
     Path(Cow<'a, Path>),
 }
 
+/// Build each target, running up to `jobs` builds at once.
+///
+/// Every target is built even if some fail, and the error reports each one that did.
+/// The outputs are sorted by rid.
 pub fn build_cross<'a>(
     args: CargoCrossBuildArgs<'a>,
 ) -> Result<Vec<CargoBuildOutput>, CargoBuildError> {
-    args.targets
-        .into_iter()
-        .map(|(target, args)| match args {
-            CargoCrossTarget::Build { .. } => Err(CargoBuildError::UnsupportedCrossBuild),
-            CargoCrossTarget::Path(path) => match path.exists() {
-                true => Ok(CargoBuildOutput {
-                    path: path.into_owned(),
-                    target: Target::Cross(target),
-                }),
-                false => Err(CargoBuildError::MissingOutput {
-                    path: path.into_owned(),
-                }),
-            },
-        })
-        .collect::<Result<Vec<_>, CargoBuildError>>()
-        .and_then(|builds| match builds.len() {
-            0 => Err(CargoBuildError::NoValidTargets),
-            _ => Ok(builds),
-        })
+    let targets = args.targets.len();
+
+    if targets == 0 {
+        Err(CargoBuildError::NoValidTargets)?;
+    }
+
+    let jobs = args.jobs.unwrap_or(targets).min(targets).max(1);
+
+    let work_dir = &args.work_dir;
+    let target_dir = &args.target_dir;
+    let shared = shared_triples(&args.targets);
+    let cargo_args = &args.cargo_args;
+    let quiet = args.quiet;
+
+    // Each job takes targets from the queue until there are none left
+    let queue = Mutex::new(args.targets.into_iter());
+    let results = Mutex::new(Vec::with_capacity(targets));
+
+    thread::scope(|scope| for _ in 0..jobs {
+        scope.spawn(|| loop {
+            let next = queue.lock().unwrap().next();
+
+            match next {
+                Some((rid, target)) => {
+                    let target_dir = match target {
+                        CargoCrossTarget::Build { ref triple, .. } if shared.contains(triple) => {
+                            Some(target_dir.join("cargo-nuget").join(rid.rid().as_ref()))
+                        }
+                        _ => None,
+                    };
+
+                    let result =
+                        build_cross_target(work_dir, target_dir, cargo_args, quiet, &rid, target);
+
+                    results.lock().unwrap().push((rid, result));
+                }
+                None => break,
+            }
+        });
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|(a, _), (b, _)| a.rid().cmp(&b.rid()));
+
+    let mut builds = Vec::new();
+    let mut errors = Vec::new();
+
+    for (rid, result) in results {
+        match result {
+            Ok(build) => builds.push(build),
+            Err(err) => errors.push((rid, err)),
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(builds),
+        _ => Err(CargoBuildError::Targets { errors }),
+    }
+}
+
+/// Get the triples that more than one target builds.
+fn shared_triples(targets: &HashMap<Rid, CargoCrossTarget>) -> HashSet<Triple> {
+    let mut seen = HashSet::new();
+    let mut shared = HashSet::new();
+
+    for target in targets.values() {
+        if let CargoCrossTarget::Build { triple, .. } = *target {
+            if !seen.insert(triple) {
+                shared.insert(triple);
+            }
+        }
+    }
+
+    shared
+}
+
+/// Build a single target, or check a pre-built library exists.
+///
+/// The target is built in `target_dir` instead of the crate's target directory if it's given.
+fn build_cross_target<'a>(
+    work_dir: &Path,
+    target_dir: Option<PathBuf>,
+    cargo_args: &[Cow<'a, str>],
+    quiet: bool,
+    rid: &Rid,
+    target: CargoCrossTarget<'a>,
+) -> Result<CargoBuildOutput, CargoBuildError> {
+    let (path, rustc_version) = match target {
+        CargoCrossTarget::Build {
+            triple,
+            action,
+            profile,
+            output_name,
+            features,
+            toolchain,
+            env,
+            rustflags,
+            command,
+        } => {
+            let toolchain = toolchain.as_ref().map(|toolchain| toolchain.as_ref());
+
+            // A custom build command might not use the same `rustc`, so its version isn't recorded
+            let rustc_version = if command.is_empty() {
+                Some(rustc(work_dir, toolchain)?.version)
+            } else {
+                None
+            };
+
+            let prefix = rid.rid();
+
+            // The target directory is set first so it can be replaced in config
+            let env: Vec<_> = target_dir
+                .as_ref()
+                .map(|target_dir| ("CARGO_TARGET_DIR".into(), target_dir.to_string_lossy()))
+                .into_iter()
+                .chain(env)
+                .collect();
+
+            let command = CargoCommand {
+                work_dir,
+                toolchain,
+                profile: &profile,
+                triple: None,
+                packages: &[],
+                features: &features,
+                args: cargo_args,
+                env: &env,
+                rustflags: &rustflags,
+                build_command: &command,
+                log_prefix: Some(&prefix),
+                quiet,
+            };
+
+            let path = build_target(command, triple, action, &output_name)?;
+
+            (path, rustc_version)
+        }
+        CargoCrossTarget::Path(path) => (path.into_owned(), None),
+    };
+
+    match path.exists() {
+        true => Ok(CargoBuildOutput {
+            path,
+            target: Target::Cross(rid.clone()),
+            rustc_version,
+        }),
+        false => Err(CargoBuildError::MissingOutput { path }),
+    }
+}
+
+/// Run `cargo build --target {triple}` and return the path to the build output.
+fn build_target(
+    command: CargoCommand,
+    triple: Triple,
+    action: Action,
+    output_name: &str,
+) -> Result<PathBuf, CargoBuildError> {
+    let target = triple.target();
+    let triple = triple.triple();
+
+    let command = CargoCommand {
+        triple: Some(&triple),
+        ..command
+    };
+
+    let artifacts = cargo_commands(&command, &actions(action))?;
+
+    output_path(&artifacts, output_name, target)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::env;
     use std::path::Path;
     use args::*;
     use super::*;
+    use super::super::host_triple;
+
+    /// Build the host triple with cargo, or with a custom command if one is given.
+    fn host_build(
+        output_name: &'static str,
+        command: &[&'static str],
+    ) -> CargoCrossTarget<'static> {
+        CargoCrossTarget::Build {
+            triple: host_triple(),
+            action: Action::Build,
+            profile: Profile::Debug,
+            output_name: output_name.into(),
+            features: CargoFeatures::default(),
+            toolchain: None,
+            env: vec![],
+            rustflags: vec![],
+            command: command.iter().map(|&arg| arg.into()).collect(),
+        }
+    }
 
     fn empty_args() -> CargoCrossBuildArgs<'static> {
         let p: &Path = "tests/native".as_ref();
 
         CargoCrossBuildArgs {
             work_dir: p.into(),
+            target_dir: env::current_dir().unwrap().join("tests/native/target").into(),
+            cargo_args: vec![],
             targets: HashMap::new(),
             quiet: true,
+            jobs: None,
+        }
+    }
+
+    /// Get the error for each target that failed to build.
+    fn target_errors(
+        result: Result<Vec<CargoBuildOutput>, CargoBuildError>,
+    ) -> Vec<CargoBuildError> {
+        match result {
+            Err(CargoBuildError::Targets { errors }) => {
+                errors.into_iter().map(|(_, err)| err).collect()
+            }
+            r => panic!("{:?}", r),
         }
     }
 
@@ -70,20 +281,20 @@ mod tests {
         let mut targets = HashMap::new();
 
         targets.insert(
-            CrossTarget::Windows(Arch::x64),
+            CrossTarget::Windows(Arch::x64).into(),
             CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
         );
         targets.insert(
-            CrossTarget::Linux(Arch::x64),
+            CrossTarget::Linux(Libc::Gnu, Arch::x64).into(),
             CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
         );
         targets.insert(
-            CrossTarget::MacOS(Arch::x64),
+            CrossTarget::MacOS(Arch::x64).into(),
             CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
         );
 
         let args = CargoCrossBuildArgs {
-            targets: targets,
+            targets,
             ..empty_args()
         };
 
@@ -95,27 +306,70 @@ mod tests {
         let mut targets = HashMap::new();
 
         targets.insert(
-            CrossTarget::Windows(Arch::x64),
+            CrossTarget::Windows(Arch::x64).into(),
             CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
         );
-        targets.insert(
-            CrossTarget::MacOS(Arch::x64),
-            CargoCrossTarget::Build {
-                action: Action::Build,
-                profile: Profile::Debug,
-                output_name: "some_output".into(),
-            },
-        );
+        targets.insert(host_triple().target().into(), host_build("native_test", &[]));
 
         let args = CargoCrossBuildArgs {
-            targets: targets,
+            targets,
             ..empty_args()
         };
 
-        let result = build_cross(args);
+        let builds = build_cross(args).unwrap();
 
-        match result {
-            Err(CargoBuildError::UnsupportedCrossBuild) => (),
+        assert_eq!(2, builds.len());
+
+        // A triple that's only built once uses the crate's target directory
+        let target_dir = env::current_dir()
+            .unwrap()
+            .join("tests/native/target")
+            .join(host_triple().triple().as_ref());
+
+        assert!(builds.iter().any(|build| build.path.starts_with(&target_dir)));
+    }
+
+    #[test]
+    fn cargo_cross_build_shared_triple() {
+        let host: Rid = host_triple().target().into();
+        let aot = Rid::parse(&format!("{}-aot", host.rid())).unwrap();
+
+        let build = host_build("native_test", &[]);
+
+        let mut targets = HashMap::new();
+
+        targets.insert(host.clone(), build.clone());
+        targets.insert(aot.clone(), build);
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            ..empty_args()
+        };
+
+        let builds = build_cross(args).unwrap();
+
+        // Targets that build the same triple each get their own directory
+        let target_dir = env::current_dir().unwrap().join("tests/native/target/cargo-nuget");
+
+        for (build, rid) in builds.iter().zip(vec![host, aot]) {
+            assert_eq!(Target::Cross(rid.clone()), build.target);
+            assert!(build.path.starts_with(target_dir.join(rid.rid().as_ref())));
+        }
+    }
+
+    #[test]
+    fn cargo_cross_build_missing_output() {
+        let mut targets = HashMap::new();
+
+        targets.insert(host_triple().target().into(), host_build("not_the_output", &[]));
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            ..empty_args()
+        };
+
+        match &target_errors(build_cross(args))[..] {
+            &[CargoBuildError::MissingArtifact { .. }] => (),
             r => panic!("{:?}", r),
         }
     }
@@ -137,20 +391,153 @@ mod tests {
         let mut targets = HashMap::new();
 
         targets.insert(
-            CrossTarget::Windows(Arch::x64),
+            CrossTarget::Windows(Arch::x64).into(),
             CargoCrossTarget::Path(Cow::Owned("not the output".into())),
         );
 
         let args = CargoCrossBuildArgs {
-            targets: targets,
+            targets,
             ..empty_args()
         };
 
-        let result = build_cross(args);
+        match &target_errors(build_cross(args))[..] {
+            &[CargoBuildError::MissingOutput { .. }] => (),
+            r => panic!("{:?}", r),
+        }
+    }
 
-        match result {
-            Err(CargoBuildError::MissingOutput { .. }) => (),
+    #[test]
+    fn cargo_cross_build_command() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let host = host_triple();
+
+        let mut output = test_dir!("fake-build");
+        output.push("libnative_test");
+        output.set_extension(host.target().extension());
+
+        let mut build = host_build("native_test", &["sh", "../fake-build.sh"]);
+
+        if let CargoCrossTarget::Build { ref mut env, ref mut rustflags, .. } = build {
+            *env = vec![
+                ("FAKE_BUILD_OUTPUT".into(), output.to_string_lossy().into_owned().into()),
+                ("FAKE_BUILD_LINKER".into(), "fake-cc".into()),
+            ];
+            *rustflags = vec!["-C".into(), "target-cpu=native".into()];
+        }
+
+        let mut targets = HashMap::new();
+
+        targets.insert(host.target().into(), build);
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            ..empty_args()
+        };
+
+        let builds = build_cross(args).unwrap();
+
+        assert_eq!(output, builds[0].path);
+
+        let mut built = String::new();
+        File::open(&output).unwrap().read_to_string(&mut built).unwrap();
+
+        let triple = host.triple();
+
+        assert!(built.contains(&format!("--target {}", triple)), "{}", built);
+        // Without rustflags in the environment, the flags are added to cargo config
+        if super::super::env_rustflags().is_empty() {
+            let config = format!(
+                r#"--config target.{}.rustflags=["-C", "target-cpu=native"]"#,
+                triple
+            );
+
+            assert!(built.contains(&config), "{}", built);
+        } else {
+            assert!(built.contains("-C\x1ftarget-cpu=native"), "{}", built);
+        }
+        assert!(built.contains("linker: fake-cc"), "{}", built);
+    }
+
+    #[test]
+    fn cargo_cross_build_command_fails() {
+        let mut targets = HashMap::new();
+
+        let build = host_build("native_test", &["sh", "-c", "exit 1"]);
+
+        targets.insert(host_triple().target().into(), build);
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            ..empty_args()
+        };
+
+        match &target_errors(build_cross(args))[..] {
+            &[CargoBuildError::Run] => (),
             r => panic!("{:?}", r),
         }
     }
+
+    #[test]
+    fn cargo_cross_build_jobs() {
+        let mut targets = HashMap::new();
+
+        targets.insert(
+            CrossTarget::Windows(Arch::x64).into(),
+            CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
+        );
+        targets.insert(
+            CrossTarget::MacOS(Arch::x64).into(),
+            CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
+        );
+        targets.insert(host_triple().target().into(), host_build("native_test", &[]));
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            jobs: Some(2),
+            ..empty_args()
+        };
+
+        let builds = build_cross(args).unwrap();
+
+        let rids: Vec<_> = builds.iter().filter_map(|build| build.target.rid()).collect();
+        let mut sorted = rids.clone();
+        sorted.sort();
+
+        assert_eq!(3, rids.len());
+        assert_eq!(sorted, rids);
+    }
+
+    #[test]
+    fn cargo_cross_reports_every_failed_target() {
+        let mut targets = HashMap::new();
+
+        targets.insert(
+            CrossTarget::Windows(Arch::x64).into(),
+            CargoCrossTarget::Path(Cow::Owned("not the output".into())),
+        );
+        targets.insert(
+            CrossTarget::MacOS(Arch::x64).into(),
+            CargoCrossTarget::Path(Cow::Owned("Cargo.toml".into())),
+        );
+        targets.insert(
+            CrossTarget::Linux(Libc::Gnu, Arch::x64).into(),
+            CargoCrossTarget::Path(Cow::Owned("also not the output".into())),
+        );
+
+        let args = CargoCrossBuildArgs {
+            targets,
+            jobs: Some(1),
+            ..empty_args()
+        };
+
+        let err = build_cross(args).unwrap_err();
+        let msg = err.to_string();
+
+        assert!(msg.starts_with("2 of the targets failed to build"), "{}", msg);
+        assert!(msg.contains("'linux-x64'"), "{}", msg);
+        assert!(msg.contains("'win-x64'"), "{}", msg);
+        assert!(!msg.contains("'osx-x64'"), "{}", msg);
+    }
 }
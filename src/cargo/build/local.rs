@@ -1,113 +1,106 @@
 //! Run a `cargo` command that builds some output.
 
 use std::borrow::Cow;
-use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::collections::HashMap;
+use std::path::Path;
 
-use super::{CargoBuildError, CargoBuildOutput};
-use args::{Action, CrossTarget, Profile, Target};
+use super::{actions, cargo_commands, output_path, rustc, CargoBuildError, CargoBuildOutput,
+            CargoCommand, CargoEnv, CargoFeatures};
+use args::{Action, Profile, Rid, Target, Triple};
 
 /// Args for running a `cargo` command for the native package.
 #[derive(Debug, Clone, PartialEq)]
 pub struct CargoLocalBuildArgs<'a> {
     pub work_dir: Cow<'a, Path>,
     pub output_name: Cow<'a, str>,
+    pub features: CargoFeatures<'a>,
+    /// Additional features to enable when building for a specific rid.
+    pub target_features: HashMap<Rid, Vec<Cow<'a, str>>>,
+    /// Additional args to pass to cargo.
+    pub cargo_args: Vec<Cow<'a, str>>,
+    /// The rustup toolchain to build with, like `nightly`.
+    pub toolchain: Option<Cow<'a, str>>,
+    /// The toolchain to build with for a specific rid, instead of `toolchain`.
+    pub target_toolchains: HashMap<Rid, Cow<'a, str>>,
+    /// Environment variables to set when building for a specific rid.
+    pub target_env: HashMap<Rid, CargoEnv<'a>>,
+    /// Additional flags to pass to `rustc` when building for a specific rid.
+    pub target_rustflags: HashMap<Rid, Vec<Cow<'a, str>>>,
+    /// A command to run instead of `cargo build` for a specific rid.
+    pub target_commands: HashMap<Rid, Vec<Cow<'a, str>>>,
+    pub target: Option<Triple>,
     pub quiet: bool,
     pub action: Action,
     pub profile: Profile,
 }
 
-pub fn build_local<'a>(args: CargoLocalBuildArgs<'a>) -> Result<CargoBuildOutput, CargoBuildError> {
-    let target = Target::Local;
-
-    // Run a specialised command if given, but always run `cargo build`
-    let cmds = match args.action {
-        Action::Build => vec![Action::Build],
-        action => vec![action, Action::Build],
+/// Build the crate for the host target, or a specific target triple.
+///
+/// The rid of the output is always the rid of the target that was built.
+pub fn build_local<'a>(
+    mut args: CargoLocalBuildArgs<'a>,
+) -> Result<CargoBuildOutput, CargoBuildError> {
+    let toolchain = args.toolchain.as_ref().map(|toolchain| toolchain.as_ref());
+    let rustc = rustc(&args.work_dir, toolchain)?;
+
+    let triple = match args.target {
+        Some(triple) => triple,
+        None => rustc.host_triple()?,
     };
 
-    cargo_commands(&args.work_dir, &cmds, args.profile, args.quiet)?;
+    let rid: Rid = triple.target().into();
+    let features = args.features.with(args.target_features.remove(&rid).unwrap_or_default());
+    let env = args.target_env.remove(&rid).unwrap_or_default();
+    let rustflags = args.target_rustflags.remove(&rid).unwrap_or_default();
+    let build_command = args.target_commands.remove(&rid).unwrap_or_default();
 
-    let path = output_path(
-        &args,
-        target.cross().ok_or(CargoBuildError::NoValidTargets)?,
-    );
+    // A toolchain for the rid replaces the one given on the command line
+    let (toolchain, rustc) = match args.target_toolchains.get(&rid) {
+        Some(target_toolchain) if Some(target_toolchain.as_ref()) != toolchain => {
+            let target_toolchain = target_toolchain.as_ref();
 
-    match path.exists() {
-        true => Ok(CargoBuildOutput {
-            path: path,
-            target: target,
-        }),
-        false => Err(CargoBuildError::MissingOutput { path: path }),
-    }
-}
-
-/// Get a path to the expected build output.
-fn output_path<'a>(args: &CargoLocalBuildArgs<'a>, target: CrossTarget) -> PathBuf {
-    let mut output = PathBuf::new();
-
-    let name = match target.prefix() {
-        Some(prefix) => {
-            let name = format!("{}{}", prefix, args.output_name);
-            Cow::Owned(name)
+            (Some(target_toolchain), super::rustc(&args.work_dir, Some(target_toolchain))?)
         }
-        None => Cow::Borrowed(args.output_name.as_ref()),
+        _ => (toolchain, rustc),
     };
 
-    output.push(args.work_dir.as_ref());
-    output.push("target");
-    output.push(args.profile.path());
-    output.push(name.as_ref());
-    output.set_extension(target.extension());
-
-    output
-}
-
-fn cargo_commands(
-    work_dir: &Path,
-    kinds: &[Action],
-    profile: Profile,
-    quiet: bool,
-) -> Result<(), CargoBuildError> {
-    for kind in kinds {
-        cargo_command(work_dir, *kind, profile, quiet)?;
-    }
-
-    Ok(())
-}
+    // Only pass `--target` if it was given, so the build is shared with plain `cargo build`
+    let cargo_triple = args.target.map(|triple| triple.triple());
+    let cargo_triple = cargo_triple.as_ref().map(|triple| triple.as_ref());
+
+    let command = CargoCommand {
+        work_dir: &args.work_dir,
+        toolchain,
+        profile: &args.profile,
+        triple: cargo_triple,
+        packages: &[],
+        features: &features,
+        args: &args.cargo_args,
+        env: &env,
+        rustflags: &rustflags,
+        build_command: &build_command,
+        log_prefix: None,
+        quiet: args.quiet,
+    };
 
-fn cargo_command(
-    work_dir: &Path,
-    kind: Action,
-    profile: Profile,
-    quiet: bool,
-) -> Result<(), CargoBuildError> {
-    let mut cargo = Command::new("cargo");
+    let artifacts = cargo_commands(&command, &actions(args.action))?;
 
-    cargo.current_dir(work_dir);
+    let path = output_path(&artifacts, &args.output_name, triple.target())?;
 
-    if quiet {
-        cargo.stdout(Stdio::null());
-        cargo.stderr(Stdio::null());
+    // A custom build command might not use the same `rustc`, so its version isn't recorded
+    let rustc_version = if build_command.is_empty() {
+        Some(rustc.version)
     } else {
-        cargo.stdout(Stdio::inherit());
-        cargo.stderr(Stdio::inherit());
-    }
-
-    cargo.arg(match kind {
-        Action::Build => "build",
-        Action::Test => "test",
-    });
-
-    if profile == Profile::Release {
-        cargo.arg("--release");
-    }
-
-    let output = cargo.output()?;
+        None
+    };
 
-    match output.status.success() {
-        true => Ok(()),
-        false => Err(CargoBuildError::Run),
+    match path.exists() {
+        true => Ok(CargoBuildOutput {
+            path,
+            target: Target::Cross(triple.target().into()),
+            rustc_version,
+        }),
+        false => Err(CargoBuildError::MissingOutput { path }),
     }
 }
 
@@ -115,6 +108,7 @@ fn cargo_command(
 mod tests {
     use std::path::Path;
     use super::*;
+    use super::super::{active_toolchain, host_triple};
 
     fn local_args() -> CargoLocalBuildArgs<'static> {
         let p: &Path = "tests/native".as_ref();
@@ -122,6 +116,15 @@ mod tests {
         CargoLocalBuildArgs {
             work_dir: p.into(),
             output_name: "native_test".into(),
+            features: CargoFeatures::default(),
+            target_features: HashMap::new(),
+            cargo_args: vec![],
+            toolchain: None,
+            target_toolchains: HashMap::new(),
+            target_env: HashMap::new(),
+            target_rustflags: HashMap::new(),
+            target_commands: HashMap::new(),
+            target: None,
             action: Action::Build,
             profile: Profile::Debug,
             quiet: true,
@@ -166,6 +169,199 @@ mod tests {
         build_local(args).unwrap();
     }
 
+    #[test]
+    fn cargo_build_host_rid() {
+        let args = local_args();
+
+        let output = build_local(args).unwrap();
+
+        assert_eq!(Some(host_triple().target()), output.target.cross());
+    }
+
+    #[test]
+    fn cargo_build_explicit_target() {
+        let host = host_triple();
+
+        let args = CargoLocalBuildArgs {
+            target: Some(host),
+            ..local_args()
+        };
+
+        let output = build_local(args).unwrap();
+
+        let triple = host.triple();
+
+        assert!(output.path.components().any(|c| c.as_os_str() == triple.as_ref()));
+        assert_eq!(Some(host.target()), output.target.cross());
+    }
+
+    #[test]
+    fn cargo_build_features() {
+        let args = CargoLocalBuildArgs {
+            features: CargoFeatures {
+                features: vec!["simd".into()],
+                no_default_features: true,
+                all_features: false,
+            },
+            ..local_args()
+        };
+
+        build_local(args).unwrap();
+    }
+
+    #[test]
+    fn cargo_build_unknown_feature() {
+        let args = CargoLocalBuildArgs {
+            features: CargoFeatures {
+                features: vec!["not_a_feature".into()],
+                ..CargoFeatures::default()
+            },
+            ..local_args()
+        };
+
+        match build_local(args) {
+            Err(CargoBuildError::Run) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_host_rid_features() {
+        let rid = host_triple().target().into();
+
+        let mut target_features = HashMap::new();
+        target_features.insert(rid, vec!["not_a_feature".into()]);
+
+        let args = CargoLocalBuildArgs {
+            target_features,
+            ..local_args()
+        };
+
+        match build_local(args) {
+            Err(CargoBuildError::Run) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_custom_profile() {
+        let args = CargoLocalBuildArgs {
+            profile: Profile::Custom("ci".into()),
+            ..local_args()
+        };
+
+        let output = build_local(args).unwrap();
+
+        assert!(output.path.components().any(|c| c.as_os_str() == "ci"));
+    }
+
+    #[test]
+    fn cargo_build_passes_cargo_args() {
+        let args = CargoLocalBuildArgs {
+            cargo_args: vec!["--offline".into()],
+            ..local_args()
+        };
+
+        build_local(args).unwrap();
+
+        let args = CargoLocalBuildArgs {
+            cargo_args: vec!["--not-a-cargo-arg".into()],
+            ..local_args()
+        };
+
+        match build_local(args) {
+            Err(CargoBuildError::Run) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_toolchain() {
+        let args = CargoLocalBuildArgs {
+            toolchain: Some(active_toolchain().into()),
+            ..local_args()
+        };
+
+        let output = build_local(args).unwrap();
+
+        let version = output.rustc_version.unwrap();
+
+        assert!(version.starts_with("rustc "), "{}", version);
+    }
+
+    #[test]
+    fn cargo_build_host_rid_toolchain() {
+        let rid = host_triple().target().into();
+
+        let mut target_toolchains = HashMap::new();
+        target_toolchains.insert(rid, "not-a-toolchain".into());
+
+        let args = CargoLocalBuildArgs {
+            toolchain: Some(active_toolchain().into()),
+            target_toolchains,
+            ..local_args()
+        };
+
+        match build_local(args) {
+            Err(CargoBuildError::Rustc) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_target_command() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let host = host_triple();
+        let rid: Rid = host.target().into();
+
+        let mut output = test_dir!("fake-build");
+        output.push("libnative_test");
+        output.set_extension(host.target().extension());
+
+        let mut target_env = HashMap::new();
+        target_env.insert(
+            rid.clone(),
+            vec![
+                ("FAKE_BUILD_OUTPUT".into(), output.to_string_lossy().into_owned().into()),
+                ("FAKE_BUILD_LINKER".into(), "fake-cc".into()),
+            ],
+        );
+
+        let mut target_commands = HashMap::new();
+        target_commands.insert(rid, vec!["sh".into(), "../fake-build.sh".into()]);
+
+        let args = CargoLocalBuildArgs {
+            target_env,
+            target_commands,
+            ..local_args()
+        };
+
+        let output_path = build_local(args).unwrap();
+
+        assert_eq!(output, output_path.path);
+        assert_eq!(None, output_path.rustc_version);
+
+        let mut built = String::new();
+        File::open(&output).unwrap().read_to_string(&mut built).unwrap();
+
+        assert!(built.contains("linker: fake-cc"), "{}", built);
+    }
+
+    #[test]
+    fn cargo_build_unknown_toolchain() {
+        let args = CargoLocalBuildArgs {
+            toolchain: Some("not-a-toolchain".into()),
+            ..local_args()
+        };
+
+        match build_local(args) {
+            Err(CargoBuildError::Rustc) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
     #[test]
     fn cargo_build_missing_output() {
         let args = CargoLocalBuildArgs {
@@ -176,7 +372,7 @@ mod tests {
         let result = build_local(args);
 
         match result {
-            Err(CargoBuildError::MissingOutput { .. }) => (),
+            Err(CargoBuildError::MissingArtifact { .. }) => (),
             r => panic!("{:?}", r),
         }
     }
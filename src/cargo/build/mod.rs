@@ -1,138 +1,783 @@
-use std::path::PathBuf;
+use std::env;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::borrow::Cow;
-use std::io::Error as IoError;
+use std::io::{BufRead, BufReader, Error as IoError};
+use std::process::{Command, Stdio};
+use std::str;
+use std::thread;
 use clap::ArgMatches;
-use args::{target_path_arg, Action, CrossTarget, Profile, Target, CARGO_BUILD_QUIET_ARG,
-           CARGO_WORK_DIR_ARG, RELEASE_ARG, TARGETS_ARG, TEST_ARG};
-use super::CargoConfig;
+use serde_json::{self, Value};
+use toml;
+use args::{has_target_path_arg, target_path_arg, Action, CrossTarget, Profile, Rid, Target,
+           Triple, ALL_FEATURES_ARG, CARGO_ARGS_ARG, CARGO_BUILD_QUIET_ARG, FEATURES_ARG,
+           JOBS_ARG, NO_DEFAULT_FEATURES_ARG, PROFILE_ARG, RELEASE_ARG, TARGETS_ARG, TARGET_ARG,
+           TEST_ARG, TOOLCHAIN_ARG};
+use super::{CargoConfig, CargoMetadata, CargoWorkspaceMetadata};
 
 mod local;
 mod cross;
+mod workspace;
 
 pub use self::local::*;
 pub use self::cross::*;
-
-impl Profile {
-    /// Get the path within the `target` folder for the output.
-    fn path(&self) -> &'static str {
-        match *self {
-            Profile::Debug => "debug",
-            Profile::Release => "release",
-        }
-    }
-}
+pub use self::workspace::*;
 
 impl CrossTarget {
     /// Get the platform specific extension for the build output.
     fn extension(&self) -> &'static str {
         match *self {
             CrossTarget::Windows(_) => "dll",
-            CrossTarget::Linux(_) => "so",
+            CrossTarget::Linux(..) => "so",
             CrossTarget::MacOS(_) => "dylib",
         }
     }
+}
 
-    /// Get the platform specific prefix for the build output.
-    fn prefix(&self) -> Option<&'static str> {
-        match *self {
-            CrossTarget::Windows(_) => None,
-            CrossTarget::Linux(_) => Some("lib"),
-            CrossTarget::MacOS(_) => Some("lib"),
+/// Environment variables to set for a build, like a linker to use.
+pub type CargoEnv<'a> = Vec<(Cow<'a, str>, Cow<'a, str>)>;
+
+/// The crate features to enable for a build.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CargoFeatures<'a> {
+    pub features: Vec<Cow<'a, str>>,
+    pub no_default_features: bool,
+    pub all_features: bool,
+}
+
+impl<'a> CargoFeatures<'a> {
+    /// Get these features with some additional ones enabled.
+    fn with<I>(&self, features: I) -> Self
+    where
+        I: IntoIterator<Item = Cow<'a, str>>,
+    {
+        let mut with = self.clone();
+        with.features.extend(features);
+
+        with
+    }
+}
+
+/// A build artifact reported by cargo.
+#[derive(Debug, Clone, PartialEq)]
+struct CargoArtifact {
+    name: String,
+    kinds: Vec<String>,
+    filenames: Vec<PathBuf>,
+}
+
+impl CargoArtifact {
+    /// Parse a `compiler-artifact` message from `cargo --message-format=json`.
+    fn from_message(msg: &Value) -> Option<Self> {
+        if msg.get("reason").and_then(Value::as_str) != Some("compiler-artifact") {
+            return None;
+        }
+
+        let strs = |val: Option<&Value>| -> Vec<String> {
+            val.and_then(Value::as_array)
+                .map(|vals| {
+                    vals.iter()
+                        .filter_map(Value::as_str)
+                        .map(Into::into)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let target = msg.get("target");
+
+        Some(CargoArtifact {
+            name: target
+                .and_then(|target| target.get("name"))
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .into(),
+            kinds: strs(target.and_then(|target| target.get("kind"))),
+            filenames: strs(msg.get("filenames"))
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+}
+
+/// Get the path to the dynamic library built for the target.
+///
+/// The path is taken from the artifacts cargo reported, so it doesn't matter where the
+/// target directory is or what profile was built.
+fn output_path(
+    artifacts: &[CargoArtifact],
+    output_name: &str,
+    target: CrossTarget,
+) -> Result<PathBuf, CargoBuildError> {
+    // Cargo reports library target names with `-` replaced by `_`
+    let output_name = output_name.replace("-", "_");
+
+    artifacts
+        .iter()
+        .filter(|artifact| artifact.name.replace("-", "_") == output_name)
+        .filter(|artifact| {
+            artifact
+                .kinds
+                .iter()
+                .any(|kind| kind == "cdylib" || kind == "dylib")
+        })
+        .flat_map(|artifact| artifact.filenames.iter())
+        .find(|path| path.extension().map(|ext| ext == target.extension()).unwrap_or(false))
+        .cloned()
+        .ok_or(CargoBuildError::MissingArtifact { name: output_name })
+}
+
+/// The options for running a cargo command.
+struct CargoCommand<'a> {
+    work_dir: &'a Path,
+    /// The rustup toolchain to run cargo with, or the default one for the working directory.
+    toolchain: Option<&'a str>,
+    profile: &'a Profile,
+    triple: Option<&'a str>,
+    /// The packages to build, or the package in the working directory if there are none.
+    packages: &'a [&'a str],
+    features: &'a CargoFeatures<'a>,
+    /// Additional args to pass to cargo, like `--locked`.
+    args: &'a [Cow<'a, str>],
+    /// Environment variables to set, like a linker to use.
+    env: &'a [(Cow<'a, str>, Cow<'a, str>)],
+    /// Additional flags to pass to `rustc`, as well as any already in the environment.
+    rustflags: &'a [Cow<'a, str>],
+    /// A command to run instead of `cargo build`, like `cargo zigbuild`.
+    build_command: &'a [Cow<'a, str>],
+    /// A prefix for each line of output, so output from concurrent builds can be told apart.
+    log_prefix: Option<&'a str>,
+    quiet: bool,
+}
+
+/// Run each cargo command and return the artifacts from the last one.
+fn cargo_commands(
+    command: &CargoCommand,
+    kinds: &[Action],
+) -> Result<Vec<CargoArtifact>, CargoBuildError> {
+    let mut artifacts = Vec::new();
+
+    for kind in kinds {
+        artifacts = cargo_command(command, *kind)?;
+    }
+
+    Ok(artifacts)
+}
+
+fn cargo_command(
+    command: &CargoCommand,
+    kind: Action,
+) -> Result<Vec<CargoArtifact>, CargoBuildError> {
+    let quiet = command.quiet;
+
+    let mut cargo = match (kind, command.build_command.split_first()) {
+        (Action::Build, Some((program, args))) => {
+            let mut cargo = Command::new(program.as_ref());
+
+            cargo.args(args.iter().map(|arg| arg.as_ref()));
+
+            // The toolchain can't be passed as an arg to an arbitrary command
+            if let Some(toolchain) = command.toolchain {
+                cargo.env("RUSTUP_TOOLCHAIN", toolchain);
+            }
+
+            cargo
+        }
+        _ => {
+            let mut cargo = Command::new("cargo");
+
+            if let Some(toolchain) = command.toolchain {
+                cargo.arg(format!("+{}", toolchain));
+            }
+
+            cargo.arg(match kind {
+                Action::Build => "build",
+                Action::Test => "test",
+            });
+
+            cargo
+        }
+    };
+
+    cargo.current_dir(command.work_dir);
+
+    let env_rustflags = env_rustflags();
+
+    // Rustflags in the environment replace any in cargo config, so extra flags are added to them
+    // The encoded flags take precedence over `RUSTFLAGS` and can contain spaces
+    if !command.rustflags.is_empty() && !env_rustflags.is_empty() {
+        let mut rustflags = env_rustflags.clone();
+        rustflags.extend(command.rustflags.iter().map(|flag| flag.to_string()));
+
+        cargo.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
+    }
+
+    for (key, val) in command.env {
+        cargo.env(key.as_ref(), val.as_ref());
+    }
+
+    // Messages are read from stdout, human readable output is written to stderr
+    cargo.stdout(Stdio::piped());
+
+    match (quiet, command.log_prefix) {
+        (true, _) => cargo.stderr(Stdio::null()),
+        (false, Some(_)) => cargo.stderr(Stdio::piped()),
+        (false, None) => cargo.stderr(Stdio::inherit()),
+    };
+
+    cargo.arg("--message-format=json");
+
+    match *command.profile {
+        Profile::Debug => (),
+        Profile::Release => {
+            cargo.arg("--release");
+        }
+        Profile::Custom(ref profile) => {
+            cargo.arg("--profile");
+            cargo.arg(profile);
         }
     }
+
+    if let Some(triple) = command.triple {
+        cargo.arg("--target");
+        cargo.arg(triple);
+    }
+
+    // Otherwise extra flags are added to cargo config, which merges them with its own
+    if !command.rustflags.is_empty() && env_rustflags.is_empty() {
+        cargo.arg("--config");
+        cargo.arg(rustflags_config(command.triple, command.rustflags));
+    }
+
+    for package in command.packages {
+        cargo.arg("--package");
+        cargo.arg(package);
+    }
+
+    let features = command.features;
+
+    if !features.features.is_empty() {
+        cargo.arg("--features");
+        cargo.arg(features.features.join(","));
+    }
+
+    if features.no_default_features {
+        cargo.arg("--no-default-features");
+    }
+
+    if features.all_features {
+        cargo.arg("--all-features");
+    }
+
+    for arg in command.args {
+        cargo.arg(arg.as_ref());
+    }
+
+    debug!("running {:?}", cargo);
+
+    let mut child = cargo.spawn()?;
+
+    let prefix = command.log_prefix;
+
+    // Piped stderr is forwarded while stdout is read, so neither can block the command
+    let stderr = child.stderr.take().map(|stderr| {
+        let prefix = prefix.map(ToOwned::to_owned);
+
+        thread::spawn(move || for line in BufReader::new(stderr).lines() {
+            match line {
+                Ok(line) => eprintln!("{}", prefixed(prefix.as_ref().map(|p| p.as_ref()), &line)),
+                Err(_) => break,
+            }
+        })
+    });
+
+    let mut artifacts = Vec::new();
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let line = line?;
+
+            match serde_json::from_str::<Value>(&line) {
+                Ok(msg) => {
+                    if let Some(artifact) = CargoArtifact::from_message(&msg) {
+                        artifacts.push(artifact);
+                    } else if let Some(rendered) = msg.get("message")
+                        .and_then(|msg| msg.get("rendered"))
+                        .and_then(Value::as_str)
+                    {
+                        if !quiet {
+                            for line in rendered.lines() {
+                                eprintln!("{}", prefixed(prefix, line));
+                            }
+                        }
+                    }
+                }
+                // Lines that aren't messages, like test output, are passed through
+                Err(_) => if !quiet {
+                    println!("{}", prefixed(prefix, &line));
+                },
+            }
+        }
+    }
+
+    if let Some(stderr) = stderr {
+        let _ = stderr.join();
+    }
+
+    let status = child.wait()?;
+
+    match status.success() {
+        true => Ok(artifacts),
+        false => Err(CargoBuildError::Run),
+    }
+}
+
+/// Prefix a line of output from a cargo command, like `[linux-x64] Compiling native`.
+fn prefixed<'a>(prefix: Option<&str>, line: &'a str) -> Cow<'a, str> {
+    match prefix {
+        Some(prefix) => format!("[{}] {}", prefix, line).into(),
+        None => line.into(),
+    }
+}
+
+/// Get the flags cargo would already pass to `rustc` from the environment.
+fn env_rustflags() -> Vec<String> {
+    match (env::var("CARGO_ENCODED_RUSTFLAGS"), env::var("RUSTFLAGS")) {
+        (Ok(ref flags), _) if !flags.is_empty() => flags.split('\x1f').map(Into::into).collect(),
+        (_, Ok(flags)) => flags.split_whitespace().map(Into::into).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Get a `--config` value that adds rustflags for a target, like `target.{triple}.rustflags=[..]`.
+///
+/// Cargo joins the flags with any for the target in `.cargo/config.toml`.
+/// Like those, they replace `build.rustflags`.
+fn rustflags_config(triple: Option<&str>, rustflags: &[Cow<str>]) -> String {
+    let key = match triple {
+        Some(triple) => format!("target.{}.rustflags", triple),
+        None => "build.rustflags".into(),
+    };
+
+    let flags = rustflags
+        .iter()
+        .map(|flag| toml::Value::String(flag.to_string()))
+        .collect();
+
+    format!("{}={}", key, toml::Value::Array(flags))
+}
+
+/// The `rustc` a cargo command builds with.
+#[derive(Debug, Clone, PartialEq)]
+struct Rustc {
+    /// The target `cargo build` produces when no `--target` is given.
+    host: String,
+    /// The verbose version, like `rustc 1.20.0 (f3d6973f4 2017-08-27)`.
+    version: String,
+}
+
+impl Rustc {
+    /// Get the host target triple.
+    ///
+    /// This isn't necessarily the target this tool was built for.
+    fn host_triple(&self) -> Result<Triple, CargoBuildError> {
+        Triple::from_triple(&self.host).ok_or_else(|| {
+            CargoBuildError::UnknownHost {
+                host: self.host.clone(),
+            }
+        })
+    }
 }
 
-fn parse_targets<'a>(args: &'a ArgMatches<'a>) -> Vec<CrossTarget> {
+/// Get the `rustc` that cargo would build with in the working directory.
+///
+/// `rustc` is resolved the same way cargo resolves it, from `RUSTC` and `RUSTC_WRAPPER`.
+/// It's run in the working directory so a `rust-toolchain` file there is
+/// respected, unless a specific toolchain is given.
+fn rustc(work_dir: &Path, toolchain: Option<&str>) -> Result<Rustc, CargoBuildError> {
+    let program = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let wrapper = env::var("RUSTC_WRAPPER").ok().filter(|wrapper| !wrapper.is_empty());
+
+    rustc_at(&program, wrapper.as_ref().map(|wrapper| wrapper.as_ref()), work_dir, toolchain)
+}
+
+/// Get the `rustc` at a specific path, or on the `PATH`.
+///
+/// If a wrapper is given then `rustc` is run through it.
+fn rustc_at(
+    program: &str,
+    wrapper: Option<&str>,
+    work_dir: &Path,
+    toolchain: Option<&str>,
+) -> Result<Rustc, CargoBuildError> {
+    let mut rustc = match wrapper {
+        Some(wrapper) => {
+            let mut rustc = Command::new(wrapper);
+            rustc.arg(program);
+
+            rustc
+        }
+        None => Command::new(program),
+    };
+
+    rustc.current_dir(work_dir);
+
+    // `RUSTC` might be a specific `rustc` rather than the rustup proxy that accepts `+toolchain`
+    if let Some(toolchain) = toolchain {
+        rustc.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+
+    let output = rustc.arg("-vV").stderr(Stdio::inherit()).output()?;
+
+    if !output.status.success() {
+        Err(CargoBuildError::Rustc)?;
+    }
+
+    let output = str::from_utf8(&output.stdout).map_err(|_| CargoBuildError::Rustc)?;
+
+    let version = output.lines().next().map(|line| line.trim().to_owned());
+
+    let host = output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+
+            match (parts.next(), parts.next()) {
+                (Some("host"), Some(host)) => Some(host.trim().to_owned()),
+                _ => None,
+            }
+        })
+        .next();
+
+    match (host, version) {
+        (Some(host), Some(version)) => Ok(Rustc {
+            host,
+            version,
+        }),
+        _ => Err(CargoBuildError::Rustc),
+    }
+}
+
+/// Get the set of cargo commands to run for an action.
+///
+/// A specialised command is run if given, but `cargo build` is always run.
+fn actions(action: Action) -> Vec<Action> {
+    match action {
+        Action::Build => vec![Action::Build],
+        action => vec![action, Action::Build],
+    }
+}
+
+/// Parse the targets to build from either dotnet rids or Rust target triples.
+///
+/// Targets given as an rid use the default triple for that target, if there is one.
+/// Targets are validated when parsing args, so invalid values here are ignored.
+fn parse_targets<'a>(args: &'a ArgMatches<'a>) -> Vec<(Rid, Option<Triple>)> {
     args.values_of(TARGETS_ARG)
-        .map(Iterator::collect)
-        .unwrap_or_else(Vec::new)
         .into_iter()
-        .filter_map(|target| match CrossTarget::from_rid(target) {
-            Some(target) => Some(target),
-            None => {
-                warn!("'{}' could not be parsed to an rid", target);
-                None
-            }
+        .flatten()
+        .filter_map(|target| match Triple::from_triple(target) {
+            Some(triple) => Some((triple.target().into(), Some(triple))),
+            None => Rid::parse(target).ok().map(|rid| {
+                let triple = Triple::new(rid.target());
+
+                (rid, triple)
+            }),
+        })
+        .collect()
+}
+
+/// Get the profile to build from program input.
+fn profile<'a>(args: &'a ArgMatches<'a>) -> Profile {
+    match (args.value_of(PROFILE_ARG), args.is_present(RELEASE_ARG)) {
+        (Some(profile), _) => Profile::from_name(profile),
+        (None, true) => Profile::Release,
+        (None, false) => Profile::Debug,
+    }
+}
+
+/// Get the toolchain to build with from program input.
+fn toolchain<'a>(args: &'a ArgMatches<'a>) -> Option<Cow<'a, str>> {
+    args.value_of(TOOLCHAIN_ARG).map(Into::into)
+}
+
+/// Get the args to pass through to cargo from program input.
+fn cargo_args<'a>(args: &'a ArgMatches<'a>) -> Vec<Cow<'a, str>> {
+    args.values_of(CARGO_ARGS_ARG)
+        .map(|args| args.map(Into::into).collect())
+        .unwrap_or_default()
+}
+
+/// Get the features to build with from program input.
+fn features<'a>(args: &'a ArgMatches<'a>) -> CargoFeatures<'a> {
+    CargoFeatures {
+        features: args.values_of(FEATURES_ARG)
+            .map(|features| features.map(Into::into).collect())
+            .unwrap_or_default(),
+        no_default_features: args.is_present(NO_DEFAULT_FEATURES_ARG),
+        all_features: args.is_present(ALL_FEATURES_ARG),
+    }
+}
+
+/// Get the features to enable for each rid from toml config.
+fn target_features<'a>(cargo: &'a CargoConfig) -> HashMap<Rid, Vec<Cow<'a, str>>> {
+    cargo
+        .nuget
+        .targets
+        .iter()
+        .filter(|&(_, target)| !target.features.is_empty())
+        .map(|(rid, target)| {
+            let features = target.features.iter().map(|feature| Cow::Borrowed(feature.as_ref()));
+
+            (rid.clone(), features.collect())
+        })
+        .collect()
+}
+
+/// Borrow a list of strings from toml config.
+fn strs<'a>(strs: &'a [String]) -> Vec<Cow<'a, str>> {
+    strs.iter().map(|s| Cow::Borrowed(s.as_ref())).collect()
+}
+
+/// Get the environment variables to set for each rid from toml config.
+fn target_env<'a>(cargo: &'a CargoConfig) -> HashMap<Rid, CargoEnv<'a>> {
+    cargo
+        .nuget
+        .targets
+        .iter()
+        .filter(|&(_, target)| !target.env.is_empty())
+        .map(|(rid, target)| {
+            let env = target
+                .env
+                .iter()
+                .map(|(key, val)| (Cow::Borrowed(key.as_str()), Cow::Borrowed(val.as_str())));
+
+            (rid.clone(), env.collect())
+        })
+        .collect()
+}
+
+/// Get the flags to pass to `rustc` for each rid from toml config.
+fn target_rustflags<'a>(cargo: &'a CargoConfig) -> HashMap<Rid, Vec<Cow<'a, str>>> {
+    cargo
+        .nuget
+        .targets
+        .iter()
+        .filter(|&(_, target)| !target.rustflags.is_empty())
+        .map(|(rid, target)| (rid.clone(), strs(&target.rustflags)))
+        .collect()
+}
+
+/// Get the command to build each rid with from toml config.
+fn target_commands<'a>(cargo: &'a CargoConfig) -> HashMap<Rid, Vec<Cow<'a, str>>> {
+    cargo
+        .nuget
+        .targets
+        .iter()
+        .filter(|&(_, target)| !target.command.is_empty())
+        .map(|(rid, target)| (rid.clone(), strs(&target.command)))
+        .collect()
+}
+
+/// Get the toolchain to build each rid with from toml config.
+fn target_toolchains<'a>(cargo: &'a CargoConfig) -> HashMap<Rid, Cow<'a, str>> {
+    cargo
+        .nuget
+        .targets
+        .iter()
+        .filter_map(|(rid, target)| {
+            target
+                .toolchain
+                .as_ref()
+                .map(|toolchain| (rid.clone(), Cow::Borrowed(toolchain.as_ref())))
         })
         .collect()
 }
 
-fn target_path<'a>(args: &'a ArgMatches<'a>, target: CrossTarget) -> Option<PathBuf> {
-    let arg = target_path_arg(target);
+fn target_path<'a>(args: &'a ArgMatches<'a>, rid: &Rid) -> Option<PathBuf> {
+    let arg = target_path_arg(rid);
 
     args.value_of(arg).map(Into::into)
 }
 
 /// Build args to run a cargo command from program input and toml config.
-impl<'a> From<(&'a ArgMatches<'a>, &'a CargoConfig)> for CargoLocalBuildArgs<'a> {
-    fn from((args, cargo): (&'a ArgMatches<'a>, &'a CargoConfig)) -> Self {
+impl<'a> From<(&'a ArgMatches<'a>, &'a CargoMetadata, &'a CargoConfig)>
+    for CargoLocalBuildArgs<'a> {
+    fn from(
+        (args, metadata, cargo): (&'a ArgMatches<'a>, &'a CargoMetadata, &'a CargoConfig),
+    ) -> Self {
         let action = match args.is_present(TEST_ARG) {
             true => Action::Test,
             _ => Action::Build,
         };
 
-        let profile = match args.is_present(RELEASE_ARG) {
-            true => Profile::Release,
-            _ => Profile::Debug,
-        };
+        let profile = profile(args);
 
-        let path = match args.value_of(CARGO_WORK_DIR_ARG) {
-            Some(path) => path.into(),
-            None => PathBuf::from("."),
-        };
+        let path = metadata.package_dir();
 
         let quiet = args.is_present(CARGO_BUILD_QUIET_ARG);
 
+        let target = args.value_of(TARGET_ARG).and_then(Triple::from_triple);
+
         CargoLocalBuildArgs {
             work_dir: path.into(),
-            output_name: Cow::Borrowed(&cargo.name),
-            action: action,
-            profile: profile,
-            quiet: quiet,
+            output_name: Cow::Borrowed(&cargo.lib_name),
+            features: features(args),
+            target_features: target_features(cargo),
+            cargo_args: cargo_args(args),
+            toolchain: toolchain(args),
+            target_toolchains: target_toolchains(cargo),
+            target_env: target_env(cargo),
+            target_rustflags: target_rustflags(cargo),
+            target_commands: target_commands(cargo),
+            target,
+            action,
+            profile,
+            quiet,
         }
     }
 }
 
-/// Build args to run a cargo command from program input and toml config.
-impl<'a> From<(&'a ArgMatches<'a>, &'a CargoConfig)> for CargoCrossBuildArgs<'a> {
-    fn from((args, cargo): (&'a ArgMatches<'a>, &'a CargoConfig)) -> Self {
+/// Build args to run a cargo command for a workspace from program input and toml config.
+impl<'a> From<(&'a ArgMatches<'a>, &'a CargoWorkspaceMetadata, &'a [CargoConfig])>
+    for CargoWorkspaceBuildArgs<'a> {
+    fn from(
+        (args, metadata, cargo): (
+            &'a ArgMatches<'a>,
+            &'a CargoWorkspaceMetadata,
+            &'a [CargoConfig],
+        ),
+    ) -> Self {
         let action = match args.is_present(TEST_ARG) {
             true => Action::Test,
             _ => Action::Build,
         };
 
-        let profile = match args.is_present(RELEASE_ARG) {
-            true => Profile::Release,
-            _ => Profile::Debug,
-        };
+        let profile = profile(args);
+
+        let quiet = args.is_present(CARGO_BUILD_QUIET_ARG);
+
+        let target = args.value_of(TARGET_ARG).and_then(Triple::from_triple);
 
-        let path = match args.value_of(CARGO_WORK_DIR_ARG) {
-            Some(path) => path.into(),
-            None => PathBuf::from("."),
+        CargoWorkspaceBuildArgs {
+            work_dir: Cow::Borrowed(&metadata.workspace_root),
+            packages: cargo
+                .iter()
+                .map(|cargo| {
+                    CargoWorkspacePackage {
+                        name: Cow::Borrowed(&cargo.name),
+                        output_name: Cow::Borrowed(&cargo.lib_name),
+                        target_features: target_features(cargo),
+                        target_toolchains: target_toolchains(cargo),
+                        target_env: target_env(cargo),
+                        target_rustflags: target_rustflags(cargo),
+                        target_commands: target_commands(cargo),
+                    }
+                })
+                .collect(),
+            features: features(args),
+            cargo_args: cargo_args(args),
+            toolchain: toolchain(args),
+            target,
+            action,
+            profile,
+            quiet,
+        }
+    }
+}
+
+/// Build args to run a cargo command from program input and toml config.
+impl<'a> From<(&'a ArgMatches<'a>, &'a CargoMetadata, &'a CargoConfig)>
+    for CargoCrossBuildArgs<'a> {
+    fn from(
+        (args, metadata, cargo): (&'a ArgMatches<'a>, &'a CargoMetadata, &'a CargoConfig),
+    ) -> Self {
+        let action = match args.is_present(TEST_ARG) {
+            true => Action::Test,
+            _ => Action::Build,
         };
 
+        let profile = profile(args);
+
+        let path = metadata.package_dir();
+
         let quiet = args.is_present(CARGO_BUILD_QUIET_ARG);
 
-        let targets = parse_targets(args)
+        let features = features(args);
+        let mut target_features = target_features(cargo);
+        let mut target_env = target_env(cargo);
+        let mut target_rustflags = target_rustflags(cargo);
+        let mut target_commands = target_commands(cargo);
+
+        // Targets given on the command line replace the ones in the manifest
+        let mut targets = parse_targets(args);
+
+        if targets.is_empty() {
+            targets = cargo
+                .nuget
+                .targets
+                .keys()
+                .map(|rid| (rid.clone(), Triple::new(rid.target())))
+                .collect();
+        }
+
+        let targets = targets
             .into_iter()
-            .map(|target| {
-                let cross = match target_path(args, target) {
-                    Some(path) => CargoCrossTarget::Path(path.into()),
-                    None => CargoCrossTarget::Build {
-                        action: action,
-                        profile: profile,
-                        output_name: Cow::Borrowed(&cargo.name),
+            .filter_map(|(rid, triple)| {
+                let config = cargo.nuget.targets.get(&rid);
+
+                let path = target_path(args, &rid)
+                    .or_else(|| config.and_then(|target| target.path.clone()));
+
+                // A toolchain for the rid replaces the one given on the command line
+                let toolchain = config
+                    .and_then(|target| target.toolchain.as_ref())
+                    .map(|toolchain| Cow::Borrowed(toolchain.as_ref()))
+                    .or_else(|| toolchain(args));
+
+                let cross = match (path, triple) {
+                    (Some(path), _) => CargoCrossTarget::Path(path.into()),
+                    (None, Some(triple)) => CargoCrossTarget::Build {
+                        triple,
+                        action,
+                        profile: profile.clone(),
+                        output_name: Cow::Borrowed(&cargo.lib_name),
+                        features: features.with(target_features.remove(&rid).unwrap_or_default()),
+                        toolchain,
+                        env: target_env.remove(&rid).unwrap_or_default(),
+                        rustflags: target_rustflags.remove(&rid).unwrap_or_default(),
+                        command: target_commands.remove(&rid).unwrap_or_default(),
                     },
+                    (None, None) => {
+                        // Only some rids have a path arg, but any can set a path in the manifest
+                        match has_target_path_arg(&rid) {
+                            true => warn!(
+                                "'{}' can't be built by cargo\nPass the path to a pre-built library with `--{}` instead",
+                                rid.rid(),
+                                target_path_arg(&rid)
+                            ),
+                            false => warn!(
+                                "'{}' can't be built by cargo\nSet the path to a pre-built library with `path` in `[package.metadata.nuget.targets.{}]` instead",
+                                rid.rid(),
+                                rid.rid()
+                            ),
+                        }
+                        return None;
+                    }
                 };
 
-                (target, cross)
+                Some((rid, cross))
             })
             .collect();
 
         CargoCrossBuildArgs {
             work_dir: path.into(),
-            quiet: quiet,
-            targets: targets,
+            target_dir: Cow::Borrowed(&metadata.target_dir),
+            cargo_args: cargo_args(args),
+            quiet,
+            jobs: args.value_of(JOBS_ARG).and_then(|jobs| jobs.parse().ok()),
+            targets,
         }
     }
 }
@@ -142,6 +787,8 @@ impl<'a> From<(&'a ArgMatches<'a>, &'a CargoConfig)> for CargoCrossBuildArgs<'a>
 pub struct CargoBuildOutput {
     pub path: PathBuf,
     pub target: Target,
+    /// The verbose version of `rustc` that built the output, if it was built by cargo.
+    pub rustc_version: Option<String>,
 }
 
 quick_error!{
@@ -158,6 +805,22 @@ quick_error!{
         Run {
             display("Error running cargo build\nBuild output (if any) should be written to stderr")
         }
+        /// An error running `rustc` to get the host target and version.
+        Rustc {
+            display("Error running `rustc -vV` to get the host target and version\nCheck the toolchain is installed with `rustup toolchain list`\nOutput (if any) should be written to stderr")
+        }
+        /// Packages in a workspace need different toolchains for the same target.
+        ConflictingToolchains { rid: String, toolchains: Vec<String> } {
+            display("Packages in the workspace need different toolchains for '{}': {}\nPack them separately with `--package` instead", rid, toolchains.join(", "))
+        }
+        /// Packages in a workspace set different `env`, `rustflags` or `command` for the same target.
+        ConflictingTargetConfig { rid: String, key: &'static str } {
+            display("Packages in the workspace set different `{}` for '{}'\nPack them separately with `--package` instead", key, rid)
+        }
+        /// The host target isn't supported.
+        UnknownHost { host: String } {
+            display("The host target '{}' isn't supported\nPass a supported target triple with `--target` instead", host)
+        }
         /// An error getting a concrete target to build for.
         NoValidTargets {
             display("No valid platform targets were supplied\nThis probably means you're running on an unsupported platform\nOr didn't supply any targets to build")
@@ -166,9 +829,149 @@ quick_error!{
         MissingOutput { path: PathBuf } {
             display("Build output was expected to be at {:?} but wasn't found", path)
         }
-        /// An unsupported cross-platform build.
-        UnsupportedCrossBuild {
-            display("Cross platform builds aren't supported yet\nBuild the crate for different targets first, then pass the paths to each target")
+        /// Some targets failed to build.
+        Targets { errors: Vec<(Rid, CargoBuildError)> } {
+            display("{}", format_target_errors(errors))
+        }
+        /// Cargo didn't report a dynamic library for the crate.
+        MissingArtifact { name: String } {
+            display("Cargo didn't build a `dylib` or `cdylib` for '{}'", name)
+        }
+    }
+}
+
+/// Format the errors for each target that failed to build.
+fn format_target_errors(errors: &[(Rid, CargoBuildError)]) -> String {
+    let mut formatted = format!("{} of the targets failed to build", errors.len());
+
+    for (rid, err) in errors {
+        formatted.push_str(&format!("\n\n'{}': {}", rid.rid(), err));
+    }
+
+    formatted
+}
+
+/// Get the host triple of the `rustc` the tests build with.
+#[cfg(test)]
+fn host_triple() -> Triple {
+    rustc("tests/native".as_ref(), None).unwrap().host_triple().unwrap()
+}
+
+/// Get the name of the toolchain rustup runs for the tests.
+///
+/// Tests that pick a toolchain use this one so they don't depend on others being installed.
+#[cfg(test)]
+fn active_toolchain() -> String {
+    let rustup = Command::new("rustup")
+        .args(["show", "active-toolchain"])
+        .output()
+        .unwrap();
+
+    str::from_utf8(&rustup.stdout)
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use args::{Arch, Libc};
+    use super::*;
+
+    #[test]
+    fn rustflags_config_for_target() {
+        let rustflags = vec!["-C".into(), "link-arg=-s \"x\"".into()];
+
+        assert_eq!(
+            r#"target.x86_64-unknown-linux-gnu.rustflags=["-C", "link-arg=-s \"x\""]"#,
+            rustflags_config(Some("x86_64-unknown-linux-gnu"), &rustflags)
+        );
+        assert_eq!(r#"build.rustflags=["-C", "link-arg=-s \"x\""]"#, rustflags_config(None, &rustflags));
+    }
+
+    #[test]
+    fn rustc_path_with_toolchain() {
+        let rustup = Command::new("rustup").args(["which", "rustc"]).output().unwrap();
+        let path = str::from_utf8(&rustup.stdout).unwrap().trim();
+
+        let toolchain = active_toolchain();
+        let rustc = rustc_at(path, None, ".".as_ref(), Some(&toolchain)).unwrap();
+
+        assert!(rustc.version.starts_with("rustc "), "{}", rustc.version);
+    }
+
+    #[test]
+    fn rustc_with_wrapper() {
+        let rustc = rustc_at("rustc", Some("env"), ".".as_ref(), None).unwrap();
+
+        assert!(rustc.version.starts_with("rustc "), "{}", rustc.version);
+    }
+
+    fn artifact(msg: &str) -> CargoArtifact {
+        let msg = serde_json::from_str(msg).unwrap();
+
+        CargoArtifact::from_message(&msg).unwrap()
+    }
+
+    #[test]
+    fn parse_artifact_message() {
+        let artifact = artifact(
+            r#"{
+                "reason": "compiler-artifact",
+                "target": { "name": "my-native", "kind": ["cdylib", "rlib"] },
+                "filenames": ["/ws/target/debug/libmy_native.so", "/ws/target/debug/libmy_native.rlib"]
+            }"#,
+        );
+
+        let expected = CargoArtifact {
+            name: "my-native".into(),
+            kinds: vec!["cdylib".into(), "rlib".into()],
+            filenames: vec![
+                "/ws/target/debug/libmy_native.so".into(),
+                "/ws/target/debug/libmy_native.rlib".into(),
+            ],
+        };
+
+        assert_eq!(expected, artifact);
+    }
+
+    #[test]
+    fn ignore_other_messages() {
+        let msg = serde_json::from_str(r#"{ "reason": "build-finished", "success": true }"#).unwrap();
+
+        assert_eq!(None, CargoArtifact::from_message(&msg));
+    }
+
+    #[test]
+    fn output_path_from_artifacts() {
+        let artifacts = vec![
+            artifact(
+                r#"{
+                    "reason": "compiler-artifact",
+                    "target": { "name": "dep", "kind": ["lib"] },
+                    "filenames": ["/ws/target/debug/deps/libdep.rlib"]
+                }"#,
+            ),
+            artifact(
+                r#"{
+                    "reason": "compiler-artifact",
+                    "target": { "name": "my_native", "kind": ["cdylib"] },
+                    "filenames": ["/ws/target/debug/my_native.dll", "/ws/target/debug/my_native.dll.lib"]
+                }"#,
+            ),
+        ];
+
+        let path = output_path(&artifacts, "my-native", CrossTarget::Windows(Arch::x64)).unwrap();
+
+        assert_eq!(PathBuf::from("/ws/target/debug/my_native.dll"), path);
+
+        let linux = CrossTarget::Linux(Libc::Gnu, Arch::x64);
+
+        match output_path(&artifacts, "my-native", linux) {
+            Err(CargoBuildError::MissingArtifact { .. }) => (),
+            r => panic!("{:?}", r),
         }
     }
 }
@@ -0,0 +1,347 @@
+//! Run a `cargo` command that builds the packages in a workspace.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{actions, cargo_commands, output_path, rustc, CargoBuildError, CargoBuildOutput,
+            CargoCommand, CargoEnv, CargoFeatures};
+use args::{Action, Profile, Rid, Target, Triple};
+
+/// Args for running a single `cargo` command for several packages in a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CargoWorkspaceBuildArgs<'a> {
+    pub work_dir: Cow<'a, Path>,
+    pub packages: Vec<CargoWorkspacePackage<'a>>,
+    pub features: CargoFeatures<'a>,
+    /// Additional args to pass to cargo.
+    pub cargo_args: Vec<Cow<'a, str>>,
+    /// The rustup toolchain to build with, like `nightly`.
+    pub toolchain: Option<Cow<'a, str>>,
+    pub target: Option<Triple>,
+    pub quiet: bool,
+    pub action: Action,
+    pub profile: Profile,
+}
+
+/// A package to build in a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CargoWorkspacePackage<'a> {
+    pub name: Cow<'a, str>,
+    pub output_name: Cow<'a, str>,
+    /// Additional features of this package to enable when building for a specific rid.
+    pub target_features: HashMap<Rid, Vec<Cow<'a, str>>>,
+    /// The toolchain this package needs when building for a specific rid.
+    pub target_toolchains: HashMap<Rid, Cow<'a, str>>,
+    /// Environment variables this package needs when building for a specific rid.
+    pub target_env: HashMap<Rid, CargoEnv<'a>>,
+    /// Flags to pass to `rustc` when building this package for a specific rid.
+    pub target_rustflags: HashMap<Rid, Vec<Cow<'a, str>>>,
+    /// A command to run instead of `cargo build` for this package for a specific rid.
+    pub target_commands: HashMap<Rid, Vec<Cow<'a, str>>>,
+}
+
+/// Build the packages for the host target, or a specific target triple.
+///
+/// The packages are built together, so shared dependencies are only built once.
+/// The outputs are in the same order as the packages.
+pub fn build_workspace<'a>(
+    args: CargoWorkspaceBuildArgs<'a>,
+) -> Result<Vec<CargoBuildOutput>, CargoBuildError> {
+    let toolchain = args.toolchain.as_ref().map(|toolchain| toolchain.as_ref());
+    let rustc = rustc(&args.work_dir, toolchain)?;
+
+    let triple = match args.target {
+        Some(triple) => triple,
+        None => rustc.host_triple()?,
+    };
+
+    let cargo_triple = args.target.map(|triple| triple.triple());
+    let cargo_triple = cargo_triple.as_ref().map(|triple| triple.as_ref());
+
+    let packages: Vec<&str> = args.packages
+        .iter()
+        .map(|package| package.name.as_ref())
+        .collect();
+
+    let rid: Rid = triple.target().into();
+
+    // The packages are built by one command, so they need to agree on a toolchain for the rid
+    let mut target_toolchains: Vec<&str> = args.packages
+        .iter()
+        .filter_map(|package| package.target_toolchains.get(&rid))
+        .map(|toolchain| toolchain.as_ref())
+        .collect();
+    target_toolchains.sort();
+    target_toolchains.dedup();
+
+    let (toolchain, rustc) = match target_toolchains.len() {
+        0 => (toolchain, rustc),
+        1 if Some(target_toolchains[0]) == toolchain => (toolchain, rustc),
+        1 => (
+            Some(target_toolchains[0]),
+            super::rustc(&args.work_dir, Some(target_toolchains[0]))?,
+        ),
+        _ => Err(CargoBuildError::ConflictingToolchains {
+            rid: rid.rid().into_owned(),
+            toolchains: target_toolchains.iter().map(|&toolchain| toolchain.into()).collect(),
+        })?,
+    };
+
+    // The env, rustflags and command for the rid apply to every package, so they need to agree too
+    let env = agreed(&rid, "env", args.packages.iter().map(|package| &package.target_env))?;
+    let rustflags = agreed(
+        &rid,
+        "rustflags",
+        args.packages.iter().map(|package| &package.target_rustflags),
+    )?;
+    let build_command = agreed(
+        &rid,
+        "command",
+        args.packages.iter().map(|package| &package.target_commands),
+    )?;
+
+    // Features for a specific package are qualified with its name, like `native/simd`
+    let features = args.features.with(args.packages.iter().flat_map(|package| {
+        package
+            .target_features
+            .get(&rid)
+            .into_iter()
+            .flat_map(|features| features.iter())
+            .map(move |feature| Cow::Owned(format!("{}/{}", package.name, feature)))
+    }));
+
+    let command = CargoCommand {
+        work_dir: &args.work_dir,
+        toolchain,
+        profile: &args.profile,
+        triple: cargo_triple,
+        packages: &packages,
+        features: &features,
+        args: &args.cargo_args,
+        env,
+        rustflags,
+        build_command,
+        log_prefix: None,
+        quiet: args.quiet,
+    };
+
+    let artifacts = cargo_commands(&command, &actions(args.action))?;
+
+    // A custom build command might not use the same `rustc`, so its version isn't recorded
+    let rustc_version = if build_command.is_empty() {
+        Some(rustc.version)
+    } else {
+        None
+    };
+
+    args.packages
+        .iter()
+        .map(|package| {
+            let path = output_path(&artifacts, &package.output_name, triple.target())?;
+
+            match path.exists() {
+                true => Ok(CargoBuildOutput {
+                    path,
+                    target: Target::Cross(triple.target().into()),
+                    rustc_version: rustc_version.clone(),
+                }),
+                false => Err(CargoBuildError::MissingOutput { path }),
+            }
+        })
+        .collect()
+}
+
+/// Get the config for a rid that every package setting it agrees on.
+///
+/// Packages that don't set the config for the rid are ignored.
+fn agreed<'b, T, I>(rid: &Rid, key: &'static str, packages: I) -> Result<&'b [T], CargoBuildError>
+where
+    T: PartialEq + 'b,
+    I: Iterator<Item = &'b HashMap<Rid, Vec<T>>>,
+{
+    let mut agreed: Option<&[T]> = None;
+
+    for config in packages.filter_map(|configs| configs.get(rid)) {
+        match agreed {
+            Some(agreed) if agreed != &config[..] => {
+                Err(CargoBuildError::ConflictingTargetConfig {
+                    rid: rid.rid().into_owned(),
+                    key,
+                })?
+            }
+            _ => agreed = Some(config),
+        }
+    }
+
+    Ok(agreed.unwrap_or(&[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    fn package(name: &'static str, output_name: &'static str) -> CargoWorkspacePackage<'static> {
+        CargoWorkspacePackage {
+            name: name.into(),
+            output_name: output_name.into(),
+            target_features: HashMap::new(),
+            target_toolchains: HashMap::new(),
+            target_env: HashMap::new(),
+            target_rustflags: HashMap::new(),
+            target_commands: HashMap::new(),
+        }
+    }
+
+    fn workspace_args() -> CargoWorkspaceBuildArgs<'static> {
+        let p: &Path = "tests/workspace".as_ref();
+
+        CargoWorkspaceBuildArgs {
+            work_dir: p.into(),
+            packages: vec![
+                package("native_a", "native_a"),
+                package("native_b", "native_b_ffi"),
+            ],
+            features: CargoFeatures::default(),
+            cargo_args: vec![],
+            toolchain: None,
+            target: None,
+            action: Action::Build,
+            profile: Profile::Debug,
+            quiet: true,
+        }
+    }
+
+    #[test]
+    fn cargo_build_workspace() {
+        let outputs = build_workspace(workspace_args()).unwrap();
+
+        assert_eq!(2, outputs.len());
+        assert!(outputs[0].path.to_string_lossy().contains("native_a"));
+        assert!(outputs[1].path.to_string_lossy().contains("native_b_ffi"));
+    }
+
+    #[test]
+    fn cargo_build_workspace_conflicting_toolchains() {
+        let rid: Rid = super::super::host_triple().target().into();
+
+        let toolchain = |toolchain: &'static str| {
+            let mut toolchains = HashMap::new();
+            toolchains.insert(rid.clone(), toolchain.into());
+
+            toolchains
+        };
+
+        let args = CargoWorkspaceBuildArgs {
+            packages: vec![
+                CargoWorkspacePackage {
+                    target_toolchains: toolchain("stable"),
+                    ..package("native_a", "native_a")
+                },
+                CargoWorkspacePackage {
+                    target_toolchains: toolchain("nightly"),
+                    ..package("native_b", "native_b_ffi")
+                },
+            ],
+            ..workspace_args()
+        };
+
+        match build_workspace(args) {
+            Err(CargoBuildError::ConflictingToolchains { toolchains, .. }) => {
+                assert_eq!(vec!["nightly".to_owned(), "stable".to_owned()], toolchains)
+            }
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_workspace_conflicting_rustflags() {
+        let rid: Rid = super::super::host_triple().target().into();
+
+        let rustflags = |rustflags: &'static str| {
+            let mut target_rustflags = HashMap::new();
+            target_rustflags.insert(rid.clone(), vec![rustflags.into()]);
+
+            target_rustflags
+        };
+
+        let args = CargoWorkspaceBuildArgs {
+            packages: vec![
+                CargoWorkspacePackage {
+                    target_rustflags: rustflags("-Ctarget-cpu=native"),
+                    ..package("native_a", "native_a")
+                },
+                CargoWorkspacePackage {
+                    target_rustflags: rustflags("-Ctarget-feature=+crt-static"),
+                    ..package("native_b", "native_b_ffi")
+                },
+            ],
+            ..workspace_args()
+        };
+
+        match build_workspace(args) {
+            Err(CargoBuildError::ConflictingTargetConfig { key, .. }) => {
+                assert_eq!("rustflags", key)
+            }
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn cargo_build_workspace_target_command() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let host = super::super::host_triple();
+        let rid: Rid = host.target().into();
+
+        let mut output = test_dir!("fake-build");
+        output.push("libnative_test");
+        output.set_extension(host.target().extension());
+
+        let mut target_env = HashMap::new();
+        target_env.insert(
+            rid.clone(),
+            vec![
+                ("FAKE_BUILD_OUTPUT".into(), output.to_string_lossy().into_owned().into()),
+                ("FAKE_BUILD_LINKER".into(), "fake-cc".into()),
+            ],
+        );
+
+        let mut target_commands = HashMap::new();
+        target_commands.insert(rid, vec!["sh".into(), "../fake-build.sh".into()]);
+
+        let args = CargoWorkspaceBuildArgs {
+            packages: vec![CargoWorkspacePackage {
+                target_env,
+                target_commands,
+                ..package("native_a", "native_test")
+            }],
+            ..workspace_args()
+        };
+
+        let outputs = build_workspace(args).unwrap();
+
+        assert_eq!(output, outputs[0].path);
+        assert_eq!(None, outputs[0].rustc_version);
+
+        let mut built = String::new();
+        File::open(&output).unwrap().read_to_string(&mut built).unwrap();
+
+        assert!(built.contains("linker: fake-cc"), "{}", built);
+    }
+
+    #[test]
+    fn cargo_build_workspace_missing_output() {
+        let args = CargoWorkspaceBuildArgs {
+            packages: vec![package("native_a", "native_a"), package("common", "common")],
+            ..workspace_args()
+        };
+
+        match build_workspace(args) {
+            Err(CargoBuildError::MissingArtifact { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Locate a package and its workspace using `cargo metadata`.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use serde_json::{self, Error as JsonError, Value};
+
+use git::{self, GitRepository};
+
+/// Args for locating a package with `cargo metadata`.
+#[derive(Debug, PartialEq)]
+pub struct CargoMetadataArgs<'a> {
+    pub manifest_path: Cow<'a, Path>,
+    pub package: Option<Cow<'a, str>>,
+}
+
+/// The location of a package and its workspace.
+#[derive(Debug, PartialEq)]
+pub struct CargoMetadata {
+    pub manifest_path: PathBuf,
+    pub workspace_root: PathBuf,
+    pub target_dir: PathBuf,
+    /// The git repository the package is in, if it's in one.
+    pub git: Option<GitRepository>,
+}
+
+impl CargoMetadata {
+    /// Get the directory containing the package manifest.
+    pub fn package_dir(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// Get the path to the workspace manifest, if it's not the package manifest.
+    pub fn workspace_manifest_path(&self) -> Option<PathBuf> {
+        let path = self.workspace_root.join("Cargo.toml");
+
+        if path == self.manifest_path {
+            None
+        } else {
+            Some(path)
+        }
+    }
+}
+
+/// The location of every package in a workspace.
+#[derive(Debug, PartialEq)]
+pub struct CargoWorkspaceMetadata {
+    pub workspace_root: PathBuf,
+    pub target_dir: PathBuf,
+    pub packages: Vec<CargoMetadata>,
+}
+
+/// Run `cargo metadata` and find the package to pack.
+///
+/// If no package is given then the manifest must belong to a package,
+/// or be a workspace with only a single member.
+pub fn metadata<'a>(args: CargoMetadataArgs<'a>) -> Result<CargoMetadata, CargoMetadataError> {
+    let metadata = cargo_metadata(&args.manifest_path)?;
+
+    let packages = packages(&metadata)?;
+
+    let manifest_path = fs::canonicalize(&args.manifest_path)?;
+
+    let selected = match args.package {
+        Some(ref package) => packages.iter().find(|&&(name, _)| name == package),
+        None => packages
+            .iter()
+            .find(|&&(_, path)| Path::new(path) == manifest_path)
+            .or_else(|| match packages.len() {
+                1 => packages.first(),
+                _ => None,
+            }),
+    };
+
+    let available = || packages.iter().map(|&(name, _)| name.to_owned()).collect();
+
+    let manifest_path = match (selected, args.package) {
+        (Some(&(_, path)), _) => PathBuf::from(path),
+        (None, Some(package)) => Err(CargoMetadataError::PackageNotFound {
+            package: package.into_owned(),
+            available: available(),
+        })?,
+        (None, None) => Err(CargoMetadataError::AmbiguousPackage {
+            available: available(),
+        })?,
+    };
+
+    let git = manifest_path.parent().and_then(git::repository);
+
+    Ok(CargoMetadata {
+        manifest_path,
+        workspace_root: path(&metadata, "workspace_root")?,
+        target_dir: path(&metadata, "target_directory")?,
+        git,
+    })
+}
+
+/// Run `cargo metadata` and find every package in the workspace.
+///
+/// Any package given in the args is ignored.
+/// The git repository is read once for the workspace root and shared by its packages.
+pub fn workspace_metadata<'a>(
+    args: CargoMetadataArgs<'a>,
+) -> Result<CargoWorkspaceMetadata, CargoMetadataError> {
+    let metadata = cargo_metadata(&args.manifest_path)?;
+
+    let workspace_root = path(&metadata, "workspace_root")?;
+    let target_dir = path(&metadata, "target_directory")?;
+    let git = git::repository(&workspace_root);
+
+    let packages = packages(&metadata)?
+        .into_iter()
+        .map(|(_, manifest_path)| {
+            CargoMetadata {
+                manifest_path: manifest_path.into(),
+                workspace_root: workspace_root.clone(),
+                target_dir: target_dir.clone(),
+                git: git.clone(),
+            }
+        })
+        .collect();
+
+    Ok(CargoWorkspaceMetadata {
+        workspace_root,
+        target_dir,
+        packages,
+    })
+}
+
+fn cargo_metadata(manifest_path: &Path) -> Result<Value, CargoMetadataError> {
+    let output = Command::new("cargo")
+        .arg("metadata")
+        .arg("--format-version=1")
+        .arg("--no-deps")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        Err(CargoMetadataError::Run)?;
+    }
+
+    let metadata = serde_json::from_slice(&output.stdout)?;
+
+    Ok(metadata)
+}
+
+/// Get a path value from the metadata.
+fn path(metadata: &Value, key: &'static str) -> Result<PathBuf, CargoMetadataError> {
+    metadata
+        .get(key)
+        .and_then(Value::as_str)
+        .map(PathBuf::from)
+        .ok_or(CargoMetadataError::Missing { key })
+}
+
+/// Get the name and manifest path of each package in the metadata.
+///
+/// Metadata is read with `--no-deps`, so these are only the packages in the workspace.
+fn packages(metadata: &Value) -> Result<Vec<(&str, &str)>, CargoMetadataError> {
+    let packages = metadata
+        .get("packages")
+        .and_then(Value::as_array)
+        .ok_or(CargoMetadataError::Missing { key: "packages" })?
+        .iter()
+        .filter_map(|pkg| {
+            let name = pkg.get("name").and_then(Value::as_str);
+            let manifest_path = pkg.get("manifest_path").and_then(Value::as_str);
+
+            name.and_then(|name| manifest_path.map(|path| (name, path)))
+        })
+        .collect();
+
+    Ok(packages)
+}
+
+quick_error!{
+    /// An error encountered while reading Cargo metadata.
+    #[derive(Debug)]
+    pub enum CargoMetadataError {
+        /// An io-related error running `cargo metadata`.
+        Io(err: IoError) {
+            cause(err)
+            display("Error running cargo metadata\nCaused by: {}", err)
+            from()
+        }
+        /// An error running `cargo metadata`.
+        Run {
+            display("Error running cargo metadata\nOutput (if any) should be written to stderr")
+        }
+        /// An error parsing the output of `cargo metadata`.
+        Json(err: JsonError) {
+            cause(err)
+            display("Error parsing cargo metadata\nCaused by: {}", err)
+            from()
+        }
+        /// A key was missing from the output of `cargo metadata`.
+        Missing { key: &'static str } {
+            display("Error parsing cargo metadata\nThe '{}' key wasn't found", key)
+        }
+        /// The requested package isn't in the workspace.
+        PackageNotFound { package: String, available: Vec<String> } {
+            display("The package '{}' wasn't found in the workspace\nAvailable packages are: {}", package, available.join(", "))
+        }
+        /// The manifest is a workspace with multiple packages.
+        AmbiguousPackage { available: Vec<String> } {
+            display("The manifest is a workspace with multiple packages\nPick one with `--package`: {}", available.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn native_args() -> CargoMetadataArgs<'static> {
+        let p: &Path = "tests/native/Cargo.toml".as_ref();
+
+        CargoMetadataArgs {
+            manifest_path: p.into(),
+            package: None,
+        }
+    }
+
+    #[test]
+    fn metadata_for_package() {
+        let metadata = metadata(native_args()).unwrap();
+
+        assert!(metadata.manifest_path.ends_with("tests/native/Cargo.toml"));
+        assert!(metadata.target_dir.ends_with("tests/native/target"));
+        assert_eq!(None, metadata.workspace_manifest_path());
+    }
+
+    #[test]
+    fn metadata_for_named_package() {
+        let args = CargoMetadataArgs {
+            package: Some("native_test".into()),
+            ..native_args()
+        };
+
+        let metadata = metadata(args).unwrap();
+
+        assert!(metadata.package_dir().ends_with("tests/native"));
+    }
+
+    #[test]
+    fn metadata_for_workspace_package() {
+        let p: &Path = "tests/workspace/Cargo.toml".as_ref();
+
+        let args = CargoMetadataArgs {
+            manifest_path: p.into(),
+            package: Some("native_b".into()),
+        };
+
+        let metadata = metadata(args).unwrap();
+
+        assert!(metadata.manifest_path.ends_with("tests/workspace/native_b/Cargo.toml"));
+        assert!(metadata.target_dir.ends_with("tests/workspace/target"));
+        assert!(metadata.workspace_manifest_path().unwrap().ends_with("tests/workspace/Cargo.toml"));
+    }
+
+    #[test]
+    fn metadata_for_workspace_is_ambiguous() {
+        let p: &Path = "tests/workspace/Cargo.toml".as_ref();
+
+        let args = CargoMetadataArgs {
+            manifest_path: p.into(),
+            package: None,
+        };
+
+        match metadata(args) {
+            Err(CargoMetadataError::AmbiguousPackage { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn metadata_for_workspace() {
+        let p: &Path = "tests/workspace/Cargo.toml".as_ref();
+
+        let args = CargoMetadataArgs {
+            manifest_path: p.into(),
+            package: None,
+        };
+
+        let metadata = workspace_metadata(args).unwrap();
+
+        assert!(metadata.workspace_root.ends_with("tests/workspace"));
+        assert_eq!(4, metadata.packages.len());
+        assert!(
+            metadata
+                .packages
+                .iter()
+                .any(|pkg| pkg.package_dir().ends_with("tests/workspace/native_a"))
+        );
+    }
+
+    #[test]
+    fn metadata_package_not_found() {
+        let args = CargoMetadataArgs {
+            package: Some("not_a_package".into()),
+            ..native_args()
+        };
+
+        match metadata(args) {
+            Err(CargoMetadataError::PackageNotFound { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+}
@@ -1,44 +1,145 @@
 //! Commands for interacting with Cargo and Rust projects.
 
 mod build;
+mod metadata;
 mod parse;
 mod version;
 
 pub use self::build::*;
+pub use self::metadata::*;
 pub use self::parse::*;
 pub use self::version::*;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use clap::ArgMatches;
 
-use args::CARGO_WORK_DIR_ARG;
+use args::{CARGO_WORK_DIR_ARG, MANIFEST_PATH_ARG, NUPKG_DIR_ARG, PACKAGE_ARG, VERSION_ARG,
+           VERSION_FROM_GIT_ARG, VERSION_LABEL_ARG, VERSION_OVERRIDE_ARG, VERSION_STRATEGY_ARG};
 
-/// Build args to parse toml from program input.
-impl<'a> From<&'a ArgMatches<'a>> for CargoParseArgs<'a> {
+/// Build args to find the package from program input.
+impl<'a> From<&'a ArgMatches<'a>> for CargoMetadataArgs<'a> {
     fn from(args: &'a ArgMatches<'a>) -> Self {
-        let path = match args.value_of(CARGO_WORK_DIR_ARG) {
-            Some(work_dir) => {
+        let path = match (args.value_of(MANIFEST_PATH_ARG), args.value_of(CARGO_WORK_DIR_ARG)) {
+            (Some(manifest_path), _) => PathBuf::from(manifest_path),
+            (None, Some(work_dir)) => {
                 let mut path = PathBuf::new();
                 path.push(work_dir);
                 path.push("Cargo");
                 path.set_extension("toml");
 
-                path.to_string_lossy().into_owned().into()
+                path
             }
-            None => "Cargo.toml".into(),
+            (None, None) => "Cargo.toml".into(),
         };
 
+        CargoMetadataArgs {
+            manifest_path: path.into(),
+            package: args.value_of(PACKAGE_ARG).map(Into::into),
+        }
+    }
+}
+
+/// Build args to parse toml from the package and its workspace.
+impl<'a> From<&'a CargoMetadata> for CargoParseArgs<'a> {
+    fn from(metadata: &'a CargoMetadata) -> Self {
+        let workspace = metadata.workspace_manifest_path().map(|path| {
+            CargoBufKind::FromFile {
+                path: path.to_string_lossy().into_owned().into(),
+            }
+        });
+
         CargoParseArgs {
-            buf: CargoBufKind::FromFile { path: path },
+            buf: CargoBufKind::FromFile {
+                path: metadata.manifest_path.to_string_lossy(),
+            },
+            workspace,
+            git: metadata.git.as_ref(),
         }
     }
 }
 
-/// Build args to add a dev tag from toml config.
-impl<'a> From<&'a CargoConfig> for CargoLocalVersionArgs<'a> {
-    fn from(cargo: &'a CargoConfig) -> Self {
-        CargoLocalVersionArgs {
-            version: &cargo.version,
+/// Build args to parse toml from each package in a workspace.
+impl<'a> From<&'a CargoWorkspaceMetadata> for CargoParseWorkspaceArgs<'a> {
+    fn from(metadata: &'a CargoWorkspaceMetadata) -> Self {
+        CargoParseWorkspaceArgs {
+            packages: metadata.packages.iter().map(Into::into).collect(),
         }
     }
 }
+
+/// Build args to generate a version from program input and toml config.
+///
+/// The strategy is used if one isn't given on the command line or in the manifest.
+impl<'a> From<(&'a ArgMatches<'a>, &'a CargoMetadata, &'a CargoConfig, CargoVersionStrategy)>
+    for CargoLocalVersionArgs<'a> {
+    fn from(
+        (args, metadata, cargo, strategy): (
+            &'a ArgMatches<'a>,
+            &'a CargoMetadata,
+            &'a CargoConfig,
+            CargoVersionStrategy,
+        ),
+    ) -> Self {
+        version_args(args, &metadata.workspace_root, &metadata.target_dir, cargo, strategy)
+    }
+}
+
+/// Build args to generate a version for a package in a workspace.
+impl<'a> From<(&'a ArgMatches<'a>, &'a CargoWorkspaceMetadata, &'a CargoConfig, CargoVersionStrategy)>
+    for CargoLocalVersionArgs<'a> {
+    fn from(
+        (args, metadata, cargo, strategy): (
+            &'a ArgMatches<'a>,
+            &'a CargoWorkspaceMetadata,
+            &'a CargoConfig,
+            CargoVersionStrategy,
+        ),
+    ) -> Self {
+        version_args(args, &metadata.workspace_root, &metadata.target_dir, cargo, strategy)
+    }
+}
+
+fn version_args<'a>(
+    args: &'a ArgMatches<'a>,
+    workspace_root: &'a Path,
+    target_dir: &'a Path,
+    cargo: &'a CargoConfig,
+    strategy: CargoVersionStrategy,
+) -> CargoLocalVersionArgs<'a> {
+    let strategy = args.value_of(VERSION_STRATEGY_ARG)
+        .and_then(CargoVersionStrategy::from_name)
+        .or(cargo.nuget.version_strategy)
+        .unwrap_or(strategy);
+
+    let label = args.value_of(VERSION_LABEL_ARG)
+        .or(cargo.nuget.version_label.as_ref().map(AsRef::as_ref))
+        .unwrap_or("dev");
+
+    // The counter is kept with the packages if they're saved to a feed
+    let mut counter_path = match args.value_of(NUPKG_DIR_ARG) {
+        Some(nupkg_dir) => PathBuf::from(nupkg_dir),
+        None => target_dir.into(),
+    };
+
+    counter_path.push(".cargo-nuget");
+    counter_path.push(&cargo.name);
+    counter_path.set_extension("counter");
+
+    let from_git = args.is_present(VERSION_FROM_GIT_ARG);
+
+    let git_tag = match (from_git, args.is_present(VERSION_OVERRIDE_ARG)) {
+        (false, _) => None,
+        (true, false) => Some(CargoGitTagVersion::Match),
+        (true, true) => Some(CargoGitTagVersion::Override),
+    };
+
+    CargoLocalVersionArgs {
+        version: &cargo.version,
+        explicit: args.value_of(VERSION_ARG).map(Into::into),
+        git_tag,
+        strategy,
+        label: label.into(),
+        work_dir: workspace_root.into(),
+        counter_path: counter_path.into(),
+    }
+}
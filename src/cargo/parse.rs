@@ -1,9 +1,17 @@
 use std::str::{self, Utf8Error};
-use std::collections::BTreeMap;
 use std::io::{Error as IoError, Read};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs::File;
-use toml::{Parser, ParserError, Value};
+use std::path::{Path, PathBuf};
+use toml::{self, Value};
+use toml::de::Error as TomlError;
+use toml::value::Table;
+
+use args::{Rid, RidError};
+use git::GitRepository;
+use super::CargoVersionStrategy;
 
 macro_rules! toml_val {
     ($toml:ident [ $key:expr ] . $cast:ident ( )) => ({
@@ -14,9 +22,14 @@ macro_rules! toml_val {
 /// Args for parsing a `Cargo.toml` package metadata file.
 ///
 /// The source can either be a relative filepath or a byte buffer.
+/// If the package is a member of a workspace then the workspace `Cargo.toml`
+/// is used to resolve keys like `version.workspace = true`.
 #[derive(Debug, PartialEq)]
 pub struct CargoParseArgs<'a> {
     pub buf: CargoBufKind<'a>,
+    pub workspace: Option<CargoBufKind<'a>>,
+    /// The git repository the package is in, if it's in one.
+    pub git: Option<&'a GitRepository>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,26 +38,124 @@ pub enum CargoBufKind<'a> {
     FromBuf { buf: Cow<'a, [u8]> },
 }
 
+/// Args for parsing the `Cargo.toml` of each package in a workspace.
+#[derive(Debug, PartialEq)]
+pub struct CargoParseWorkspaceArgs<'a> {
+    pub packages: Vec<CargoParseArgs<'a>>,
+}
+
 /// The parsed `Cargo.toml` metadata.
 #[derive(Debug, PartialEq)]
 pub struct CargoConfig {
     pub name: String,
+    /// The name of the library target, which the native library is named after.
+    ///
+    /// This is `[lib] name` if it's given, otherwise the package name with `-` replaced by `_`.
+    pub lib_name: String,
     pub version: String,
     pub authors: Vec<String>,
-    pub repository: String,
-    pub description: String,
+    /// The repository url.
+    ///
+    /// If it's not in the manifest then the remote of the local git repository is used.
+    pub repository: Option<String>,
+    pub description: Option<String>,
+    /// An SPDX license expression.
+    pub license: Option<String>,
+    pub license_file: Option<PathBuf>,
+    pub homepage: Option<String>,
+    pub readme: Option<PathBuf>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    /// Dependencies on other crates by path, like the ones in a workspace.
+    pub path_dependencies: Vec<CargoPathDependency>,
+    /// The local git repository the package is in.
+    pub git: Option<GitRepository>,
+    pub nuget: CargoNugetConfig,
+}
+
+/// A dependency on another crate by path, like `other = { path = "../other", version = "1.0" }`.
+#[derive(Debug, PartialEq)]
+pub struct CargoPathDependency {
+    /// The name of the package depended on.
+    pub name: String,
+    /// The Cargo version requirement, if there is one.
+    pub version: Option<String>,
+}
+
+/// NuGet specific config from the `[package.metadata.nuget]` table.
+///
+/// Paths to files are relative to the directory containing the `Cargo.toml`.
+#[derive(Debug, Default, PartialEq)]
+pub struct CargoNugetConfig {
+    /// The package id, if it's different from the crate name.
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub owners: Vec<String>,
+    pub copyright: Option<String>,
+    pub tags: Vec<String>,
+    /// A path to an image to use as the package icon.
+    pub icon: Option<PathBuf>,
+    /// An SPDX license expression.
+    pub license: Option<String>,
+    pub dependencies: Vec<CargoNugetDependency>,
+    /// Whether to depend on `Microsoft.NETCore.Platforms`.
+    ///
+    /// Older SDKs need it to find native libs, but newer ones don't.
+    pub platforms_dependency: Option<bool>,
+    /// Additional files to include in the package.
+    pub files: Vec<CargoNugetFile>,
+    /// Targets to build when none are given on the command line.
+    pub targets: HashMap<Rid, CargoNugetTarget>,
+    pub version_strategy: Option<CargoVersionStrategy>,
+    /// The pre-release label to add to generated versions.
+    pub version_label: Option<String>,
+}
+
+/// A NuGet package dependency, like `Some.Package = "1.0.0"`.
+///
+/// Dependencies can also be tables, like
+/// `Some.Package = { version = "1.0.0", target-framework = "netstandard2.0" }`.
+#[derive(Debug, PartialEq)]
+pub struct CargoNugetDependency {
+    pub id: String,
+    /// A NuGet version range.
+    pub version: String,
+    /// The framework the dependency is needed for, or all frameworks if there isn't one.
+    pub target_framework: Option<String>,
+}
+
+/// An additional file to include in the package, like `{ src = "README.md", target = "docs" }`.
+///
+/// The target is the folder in the package to put the file in.
+#[derive(Debug, PartialEq)]
+pub struct CargoNugetFile {
+    pub src: PathBuf,
+    pub target: String,
+}
+
+/// Config for a specific rid, like `[package.metadata.nuget.targets.linux-x64]`.
+#[derive(Debug, Default, PartialEq)]
+pub struct CargoNugetTarget {
+    /// A path to a pre-built library to use instead of building one.
+    pub path: Option<PathBuf>,
+    /// Additional crate features to enable when building for this rid.
+    pub features: Vec<String>,
+    /// The rustup toolchain to build this rid with, like `nightly`.
+    pub toolchain: Option<String>,
+    /// Environment variables to set when building this rid, like a linker to use.
+    pub env: BTreeMap<String, String>,
+    /// Additional flags to pass to `rustc` when building this rid.
+    pub rustflags: Vec<String>,
+    /// A command to run instead of `cargo build`, like `["cargo", "zigbuild"]`.
+    pub command: Vec<String>,
 }
 
 /// Parse `CargoConfig` from the given source.
 pub fn parse_toml<'a>(args: CargoParseArgs<'a>) -> Result<CargoConfig, CargoParseError> {
-    let buf = get_buf(args.buf)?;
-
-    let utf8 = str::from_utf8(&buf)?;
-    let mut parser = Parser::new(utf8);
+    let dir = manifest_dir(&args.buf);
+    let git = args.git;
 
-    let toml = parser.parse().ok_or(CargoParseError::Toml {
-        errs: parser.errors,
-    })?;
+    let toml = read_package_toml(args)?;
 
     let is_dylib = is_dylib(&toml).unwrap_or(false);
 
@@ -52,33 +163,451 @@ pub fn parse_toml<'a>(args: CargoParseArgs<'a>) -> Result<CargoConfig, CargoPars
         Err(CargoParseError::NotADyLib)?;
     }
 
-    let config = parse_config_from_toml(&toml)?;
+    let config = parse_package_config(&toml, dir.as_ref().map(AsRef::as_ref), git)?;
+
+    Ok(config)
+}
+
+/// Parse `CargoConfig` for each package in a workspace that can be packed.
+///
+/// Packages that aren't dynamic libraries are ignored, and so are packages
+/// that opt out with `package.metadata.nuget.skip = true`.
+pub fn parse_workspace_toml<'a>(
+    args: CargoParseWorkspaceArgs<'a>,
+) -> Result<Vec<CargoConfig>, CargoParseError> {
+    let mut configs = Vec::new();
+
+    for args in args.packages {
+        let dir = manifest_dir(&args.buf);
+        let git = args.git;
+
+        let toml = read_package_toml(args)?;
+
+        let name = toml.get("package")
+            .and_then(|pkg| pkg.get("name"))
+            .and_then(|name| name.as_str())
+            .unwrap_or("");
+
+        // Only the packages that will be packed need valid config
+        match (is_dylib(&toml).unwrap_or(false), is_skipped(&toml)) {
+            (false, _) => debug!("skipping '{}' because it isn't a dylib", name),
+            (true, true) => info!(
+                "skipping '{}' because it sets `package.metadata.nuget.skip`",
+                name
+            ),
+            (true, false) => {
+                configs.push(parse_package_config(&toml, dir.as_ref().map(AsRef::as_ref), git)?)
+            }
+        }
+    }
+
+    match configs.len() {
+        0 => Err(CargoParseError::NoDyLibs),
+        _ => Ok(configs),
+    }
+}
+
+/// Read a package toml tree, with keys inherited from its workspace.
+fn read_package_toml<'a>(args: CargoParseArgs<'a>) -> Result<Table, CargoParseError> {
+    let mut toml = read_toml(args.buf)?;
+
+    // A package at the root of a workspace can inherit from its own `[workspace]`
+    let (workspace, workspace_dir) = match args.workspace {
+        Some(buf) => {
+            // The workspace dir is made absolute so it's not joined onto the package dir
+            let dir = match (manifest_dir(&buf), env::current_dir()) {
+                (Some(dir), Ok(cwd)) => Some(cwd.join(dir)),
+                _ => None,
+            };
+
+            (Some(read_toml(buf)?), dir)
+        }
+        None => (None, None),
+    };
+
+    inherit_workspace(&mut toml, workspace.as_ref(), workspace_dir.as_ref().map(AsRef::as_ref))?;
+
+    Ok(toml)
+}
+
+/// Get the directory that paths in the manifest are relative to.
+///
+/// This is only known if the manifest is read from a file.
+fn manifest_dir<'a>(buf: &CargoBufKind<'a>) -> Option<PathBuf> {
+    match *buf {
+        CargoBufKind::FromFile { ref path } => Path::new(path.as_ref()).parent().map(Into::into),
+        CargoBufKind::FromBuf { .. } => None,
+    }
+}
+
+/// Read a buffer as a toml tree.
+fn read_toml<'a>(buf: CargoBufKind<'a>) -> Result<Table, CargoParseError> {
+    let buf = get_buf(buf)?;
+
+    let utf8 = str::from_utf8(&buf)?;
+
+    let toml = toml::from_str(utf8)?;
+
+    Ok(toml)
+}
+
+/// Replace keys like `version.workspace = true` with the value from `[workspace.package]`.
+///
+/// Dependencies like `dep.workspace = true` are replaced with the one in `[workspace.dependencies]`.
+/// Paths like `readme` in `[workspace.package]` are relative to `workspace_dir`, if it's known.
+fn inherit_workspace(
+    toml: &mut Table,
+    workspace: Option<&Table>,
+    workspace_dir: Option<&Path>,
+) -> Result<(), CargoKeyError> {
+    let ws_pkg = workspace
+        .unwrap_or(toml)
+        .get("workspace")
+        .and_then(|ws| ws.get("package"))
+        .and_then(|pkg| pkg.as_table())
+        .cloned();
+
+    let ws_deps = workspace
+        .unwrap_or(toml)
+        .get("workspace")
+        .and_then(|ws| ws.get("dependencies"))
+        .and_then(|deps| deps.as_table())
+        .cloned();
+
+    if let Some(&mut Value::Table(ref mut deps)) = toml.get_mut("dependencies") {
+        for (name, dependency) in deps.iter_mut() {
+            let ws_dep = match dependency.get("workspace").and_then(|ws| ws.as_bool()) {
+                Some(true) => ws_deps.as_ref().and_then(|ws_deps| ws_deps.get(name)),
+                _ => continue,
+            };
+
+            // A workspace dependency can be just a version, like `dep = "1.0"`
+            let mut inherited = match ws_dep {
+                Some(Value::String(version)) => {
+                    let mut inherited = Table::new();
+                    inherited.insert("version".into(), Value::String(version.clone()));
+
+                    inherited
+                }
+                Some(Value::Table(inherited)) => inherited.clone(),
+                _ => Err(CargoKeyError::MissingWorkspace {
+                    key: format!("dependencies.{}", name),
+                })?,
+            };
+
+            if let Some(dependency) = dependency.as_table() {
+                for (key, value) in dependency.iter().filter(|&(key, _)| key != "workspace") {
+                    inherited.insert(key.clone(), value.clone());
+                }
+            }
+
+            *dependency = Value::Table(inherited);
+        }
+    }
+
+    let pkg = match toml.get_mut("package") {
+        Some(&mut Value::Table(ref mut pkg)) => pkg,
+        _ => return Ok(()),
+    };
+
+    for (key, value) in pkg.iter_mut() {
+        let inherited = value
+            .get("workspace")
+            .and_then(|ws| ws.as_bool())
+            .unwrap_or(false);
+
+        if inherited {
+            *value = ws_pkg
+                .as_ref()
+                .and_then(|ws_pkg| ws_pkg.get(key))
+                .cloned()
+                .ok_or_else(|| CargoKeyError::MissingWorkspace { key: key.clone() })?;
+
+            // Rebase paths onto the workspace root, so they don't resolve from the package
+            let is_path = matches!(key.as_str(), "readme" | "license-file");
+
+            if let (true, Some(dir), Value::String(path)) = (is_path, workspace_dir, value) {
+                *path = dir.join(&path).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse the toml tree for a package to a `CargoConfig`.
+///
+/// If the package is in a git repository then its remote is used as the repository,
+/// unless one is given in the manifest.
+fn parse_package_config(
+    toml: &Table,
+    dir: Option<&Path>,
+    git: Option<&GitRepository>,
+) -> Result<CargoConfig, CargoKeyError> {
+    let mut config = parse_config_from_toml(toml, dir.unwrap_or(Path::new("")))?;
+
+    config.git = git.cloned();
+
+    if config.repository.is_none() {
+        config.repository = config.git.as_ref().and_then(|git| git.url.clone());
+    }
+
+    if let Some(GitRepository { dirty: Some(true), .. }) = config.git {
+        warn!(
+            "the git repository for '{}' has uncommitted changes, so the package may not match its commit",
+            config.name
+        );
+    }
 
     Ok(config)
 }
 
 /// Parse the toml tree to a `CargoConfig`.
-fn parse_config_from_toml(toml: &BTreeMap<String, Value>) -> Result<CargoConfig, CargoKeyError> {
+fn parse_config_from_toml(toml: &Table, dir: &Path) -> Result<CargoConfig, CargoKeyError> {
     let pkg = toml_val!(toml["package"].as_table())?;
     let name = toml_val!(pkg["name"].as_str())?.to_owned();
+    let lib_name = toml.get("lib")
+        .and_then(|lib| lib.get("name"))
+        .and_then(|name| name.as_str())
+        .unwrap_or(&name)
+        .replace("-", "_");
     let ver = toml_val!(pkg["version"].as_str())?.to_owned();
-    let repository = toml_val!(pkg["repository"].as_str())?.to_owned();
-    let desc = toml_val!(pkg["description"].as_str())?.to_owned();
-    let authors = toml_val!(pkg["authors"].as_slice())?
+    let authors = toml_val!(pkg["authors"].as_array())?
         .iter()
         .filter_map(|a| a.as_str())
         .map(|a| a.to_owned())
         .collect();
+    let string = |key: &str| pkg.get(key).and_then(Value::as_str).map(ToOwned::to_owned);
+    // Optional keys that are empty are the same as missing ones
+    let non_empty = |key: &str| string(key).and_then(|val| match val.len() {
+        0 => None,
+        _ => Some(val),
+    });
+    // `readme = true` is the same as `readme = "README.md"`
+    let readme = match pkg.get("readme") {
+        Some(Value::String(readme)) => Some(dir.join(readme)),
+        Some(&Value::Boolean(true)) => Some(dir.join("README.md")),
+        _ => None,
+    };
+    let nuget = parse_nuget_from_toml(pkg, dir)?;
 
     Ok(CargoConfig {
-        name: name,
+        name,
+        lib_name,
         version: ver,
-        authors: authors,
-        repository: repository,
-        description: desc,
+        authors,
+        repository: non_empty("repository"),
+        description: non_empty("description"),
+        license: string("license"),
+        license_file: string("license-file").map(|path| dir.join(path)),
+        homepage: string("homepage"),
+        readme,
+        keywords: strings(pkg.get("keywords")),
+        categories: strings(pkg.get("categories")),
+        path_dependencies: parse_path_dependencies(toml),
+        git: None,
+        nuget,
+    })
+}
+
+/// Get the dependencies in the `[dependencies]` table that are found by path.
+///
+/// Renamed dependencies, like `other = { package = "some-other", path = "../other" }`,
+/// use the name of the package.
+fn parse_path_dependencies(toml: &Table) -> Vec<CargoPathDependency> {
+    let dependencies = match toml.get("dependencies").and_then(Value::as_table) {
+        Some(dependencies) => dependencies,
+        None => return vec![],
+    };
+
+    dependencies
+        .iter()
+        .filter_map(|(name, dependency)| {
+            let dependency = dependency.as_table()?;
+
+            dependency.get("path")?;
+
+            let name = dependency.get("package").and_then(Value::as_str).unwrap_or(name);
+
+            Some(CargoPathDependency {
+                name: name.to_owned(),
+                version: dependency.get("version").and_then(Value::as_str).map(ToOwned::to_owned),
+            })
+        })
+        .collect()
+}
+
+/// Parse the `[package.metadata.nuget]` table to a `CargoNugetConfig`.
+///
+/// The table is optional, and so are all of its keys.
+fn parse_nuget_from_toml(pkg: &Table, dir: &Path) -> Result<CargoNugetConfig, CargoKeyError> {
+    let nuget = match pkg.get("metadata")
+        .and_then(|meta| meta.get("nuget"))
+        .and_then(|nuget| nuget.as_table())
+    {
+        Some(nuget) => nuget,
+        None => return Ok(CargoNugetConfig::default()),
+    };
+
+    let string = |key: &str| nuget.get(key).and_then(Value::as_str).map(ToOwned::to_owned);
+    let table = |key: &str| nuget.get(key).and_then(Value::as_table).cloned().unwrap_or_default();
+
+    let dependencies = table("dependencies")
+        .into_iter()
+        .map(|(id, dependency)| parse_nuget_dependency(id, &dependency))
+        .collect::<Result<_, _>>()?;
+
+    let files = nuget
+        .get("files")
+        .and_then(Value::as_array)
+        .map(|files| {
+            files
+                .iter()
+                .enumerate()
+                .map(|(i, file)| parse_nuget_file(i, file, dir))
+                .collect::<Result<_, _>>()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))?;
+
+    let mut targets = HashMap::new();
+    for (rid, target) in table("targets") {
+        let path = target.get("path").and_then(Value::as_str).map(|path| dir.join(path));
+
+        let env = target
+            .get("env")
+            .and_then(Value::as_table)
+            .map(|env| {
+                env.iter()
+                    .filter_map(|(key, val)| val.as_str().map(|val| (key.clone(), val.to_owned())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let toolchain = target.get("toolchain").and_then(Value::as_str).map(ToOwned::to_owned);
+
+        let rid = Rid::parse(&rid).map_err(|err| CargoKeyError::Rid { key: rid, err })?;
+
+        targets.insert(
+            rid,
+            CargoNugetTarget {
+                path,
+                features: strings(target.get("features")),
+                toolchain,
+                env,
+                rustflags: strings(target.get("rustflags")),
+                command: strings(target.get("command")),
+            },
+        );
+    }
+
+    let version_strategy = match string("version-strategy") {
+        Some(strategy) => Some(
+            CargoVersionStrategy::from_name(&strategy).ok_or(CargoKeyError::Invalid {
+                key: "version-strategy".into(),
+                value: strategy,
+            })?,
+        ),
+        None => None,
+    };
+
+    Ok(CargoNugetConfig {
+        id: string("id"),
+        title: string("title"),
+        owners: strings(nuget.get("owners")),
+        copyright: string("copyright"),
+        tags: strings(nuget.get("tags")),
+        icon: string("icon").map(|icon| dir.join(icon)),
+        license: string("license"),
+        dependencies,
+        platforms_dependency: nuget.get("platforms-dependency").and_then(Value::as_bool),
+        files,
+        targets,
+        version_strategy,
+        version_label: string("version-label"),
     })
 }
 
+/// Parse a NuGet dependency, either as a version range or a `{ version, target-framework }` table.
+fn parse_nuget_dependency(
+    id: String,
+    dependency: &Value,
+) -> Result<CargoNugetDependency, CargoKeyError> {
+    let key = format!("dependencies.{}", id);
+
+    match *dependency {
+        Value::String(ref version) => Ok(CargoNugetDependency {
+            id,
+            version: version.to_owned(),
+            target_framework: None,
+        }),
+        Value::Table(ref dependency) => {
+            let version = table_str(dependency, "version", &key)?
+                .ok_or_else(|| invalid(&format!("{}.version", key), None))?;
+            let target_framework = table_str(dependency, "target-framework", &key)?;
+
+            Ok(CargoNugetDependency {
+                id,
+                version,
+                target_framework,
+            })
+        }
+        ref dependency => Err(invalid(&key, Some(dependency))),
+    }
+}
+
+/// Parse a file to include in the package, either as a path or a `{ src, target }` table.
+fn parse_nuget_file(i: usize, file: &Value, dir: &Path) -> Result<CargoNugetFile, CargoKeyError> {
+    let key = format!("files[{}]", i);
+
+    match *file {
+        Value::String(ref src) => Ok(CargoNugetFile {
+            src: dir.join(src),
+            target: "".into(),
+        }),
+        Value::Table(ref file) => {
+            let src = table_str(file, "src", &key)?
+                .ok_or_else(|| invalid(&format!("{}.src", key), None))?;
+            let target = table_str(file, "target", &key)?;
+
+            Ok(CargoNugetFile {
+                src: dir.join(src),
+                target: target.unwrap_or_default(),
+            })
+        }
+        ref file => Err(invalid(&key, Some(file))),
+    }
+}
+
+/// Get an optional string from a table, failing if it's some other kind of value.
+fn table_str(table: &Table, name: &str, key: &str) -> Result<Option<String>, CargoKeyError> {
+    match table.get(name) {
+        Some(Value::String(val)) => Ok(Some(val.to_owned())),
+        Some(val) => Err(invalid(&format!("{}.{}", key, name), Some(val))),
+        None => Ok(None),
+    }
+}
+
+/// Get the strings in an optional array, ignoring any values that aren't strings.
+fn strings(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|vals| {
+            vals.iter()
+                .filter_map(Value::as_str)
+                .map(ToOwned::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// An invalid value for a key, or a missing one if there's no value.
+fn invalid(key: &str, value: Option<&Value>) -> CargoKeyError {
+    CargoKeyError::Invalid {
+        key: key.into(),
+        value: value.map(ToString::to_string).unwrap_or_default(),
+    }
+}
+
 /// Get a toml byte buffer.
 fn get_buf<'a>(buf: CargoBufKind<'a>) -> Result<Cow<'a, [u8]>, CargoParseError> {
     match buf {
@@ -107,10 +636,10 @@ fn get_buf<'a>(buf: CargoBufKind<'a>) -> Result<Cow<'a, [u8]>, CargoParseError>
 }
 
 /// Check if the toml specifies a dynamic library.
-fn is_dylib(toml: &BTreeMap<String, Value>) -> Result<bool, CargoParseError> {
+fn is_dylib(toml: &Table) -> Result<bool, CargoParseError> {
     let lib = toml_val!(toml["lib"].as_table())?;
 
-    let is_dylib = toml_val!(lib["crate-type"].as_slice())?
+    let is_dylib = toml_val!(lib["crate-type"].as_array())?
         .iter()
         .filter_map(|t| t.as_str())
         .any(|t| t == "dylib" || t == "cdylib");
@@ -121,6 +650,16 @@ fn is_dylib(toml: &BTreeMap<String, Value>) -> Result<bool, CargoParseError> {
     }
 }
 
+/// Check if the toml opts out of packing with `package.metadata.nuget.skip`.
+fn is_skipped(toml: &Table) -> bool {
+    toml.get("package")
+        .and_then(|pkg| pkg.get("metadata"))
+        .and_then(|meta| meta.get("nuget"))
+        .and_then(|nuget| nuget.get("skip"))
+        .and_then(|skip| skip.as_bool())
+        .unwrap_or(false)
+}
+
 quick_error!{
     /// An error encountered while parsing Cargo configuration.
     #[derive(Debug)]
@@ -128,6 +667,16 @@ quick_error!{
         Missing { key: &'static str } {
             display("The '{}' key is required, but wasn't found", key)
         }
+        Invalid { key: String, value: String } {
+            display("The '{}' key has an unsupported value '{}'", key, value)
+        }
+        Rid { key: String, err: RidError } {
+            cause(err)
+            display("The '{}' key isn't a valid rid\nCaused by: {}", key, err)
+        }
+        MissingWorkspace { key: String } {
+            display("The '{}' key is inherited from the workspace, but wasn't found in `[workspace.package]`", key)
+        }
     }
 }
 
@@ -152,13 +701,19 @@ quick_error!{
             from()
         }
         /// An error parsing the input as TOML.
-        Toml { errs: Vec<ParserError> } {
-            display("Error parsing config\nCaused by: {:?}", errs)
+        Toml(err: TomlError) {
+            cause(err)
+            display("Error parsing config\nCaused by: {}", err)
+            from()
         }
         /// The crate isn't a dynamic library.
         NotADyLib {
             display("The crate must include `dylib` in `lib.crate-type`")
         }
+        /// None of the crates in the workspace can be packed.
+        NoDyLibs {
+            display("None of the crates in the workspace include `dylib` in `lib.crate-type`")
+        }
     }
 }
 
@@ -184,27 +739,331 @@ mod tests {
             buf: CargoBufKind::FromBuf {
                 buf: toml.as_bytes().into(),
             },
+            workspace: None,
+            git: None,
         };
 
         let toml = parse_toml(args).unwrap();
 
         let expected = CargoConfig {
             name: "native".into(),
+            lib_name: "native".into(),
             version: "0.1.0".into(),
             authors: vec!["Somebody".into(), "Somebody Else".into()],
-            repository: "https://github.com/KodrAus/cargo-nuget".into(),
-            description: "".into(),
+            repository: Some("https://github.com/KodrAus/cargo-nuget".into()),
+            description: None,
+            license: None,
+            license_file: None,
+            homepage: None,
+            readme: None,
+            keywords: vec![],
+            categories: vec![],
+            path_dependencies: vec![],
+            git: None,
+            nuget: CargoNugetConfig::default(),
         };
 
         assert_eq!(expected, toml);
     }
 
+    #[test]
+    fn parse_toml_nuget_metadata() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version = "0.1.0"
+            authors = ["Somebody"]
+            repository = "https://github.com/KodrAus/cargo-nuget"
+            description = ""
+
+            [lib]
+            crate-type = ["cdylib"]
+
+            [package.metadata.nuget]
+            id = "Native.Runtime"
+            title = "Native Runtime"
+            owners = ["Someone"]
+            tags = ["native", "ffi"]
+            icon = "icon.png"
+            license = "MIT OR Apache-2.0"
+            files = ["README.md", { src = "docs/guide.md", target = "docs" }]
+            version-strategy = "manifest"
+            version-label = "beta"
+            platforms-dependency = false
+
+            [package.metadata.nuget.dependencies]
+            "Native.Managed" = "[1.0.0, 2.0.0)"
+            "Native.Interop" = { version = "1.0.0", target-framework = "netstandard2.0" }
+
+            [package.metadata.nuget.targets.linux-x64]
+            features = ["simd"]
+            toolchain = "nightly"
+            env = { CC_x86_64_unknown_linux_gnu = "clang" }
+            rustflags = ["-C", "target-cpu=native"]
+            command = ["cargo", "zigbuild"]
+
+            [package.metadata.nuget.targets.win-x64]
+            path = "prebuilt/native.dll"
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        let mut env = BTreeMap::new();
+        env.insert("CC_x86_64_unknown_linux_gnu".into(), "clang".into());
+
+        let mut targets = HashMap::new();
+        targets.insert(
+            Rid::parse("linux-x64").unwrap(),
+            CargoNugetTarget {
+                path: None,
+                features: vec!["simd".into()],
+                toolchain: Some("nightly".into()),
+                env,
+                rustflags: vec!["-C".into(), "target-cpu=native".into()],
+                command: vec!["cargo".into(), "zigbuild".into()],
+            },
+        );
+        targets.insert(
+            Rid::parse("win-x64").unwrap(),
+            CargoNugetTarget {
+                path: Some("prebuilt/native.dll".into()),
+                ..CargoNugetTarget::default()
+            },
+        );
+
+        let expected = CargoNugetConfig {
+            id: Some("Native.Runtime".into()),
+            title: Some("Native Runtime".into()),
+            owners: vec!["Someone".into()],
+            copyright: None,
+            tags: vec!["native".into(), "ffi".into()],
+            icon: Some("icon.png".into()),
+            license: Some("MIT OR Apache-2.0".into()),
+            dependencies: vec![
+                CargoNugetDependency {
+                    id: "Native.Interop".into(),
+                    version: "1.0.0".into(),
+                    target_framework: Some("netstandard2.0".into()),
+                },
+                CargoNugetDependency {
+                    id: "Native.Managed".into(),
+                    version: "[1.0.0, 2.0.0)".into(),
+                    target_framework: None,
+                },
+            ],
+            platforms_dependency: Some(false),
+            files: vec![
+                CargoNugetFile {
+                    src: "README.md".into(),
+                    target: "".into(),
+                },
+                CargoNugetFile {
+                    src: "docs/guide.md".into(),
+                    target: "docs".into(),
+                },
+            ],
+            targets,
+            version_strategy: Some(CargoVersionStrategy::Manifest),
+            version_label: Some("beta".into()),
+        };
+
+        assert_eq!(expected, toml.nuget);
+    }
+
+    #[test]
+    fn parse_toml_package_metadata() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version = "0.1.0"
+            authors = ["Somebody"]
+            repository = "https://github.com/KodrAus/cargo-nuget"
+            description = ""
+            license = "MIT OR Apache-2.0"
+            license-file = "LICENSE.txt"
+            homepage = "https://example.com"
+            readme = true
+            keywords = ["native", "ffi"]
+            categories = ["development-tools::ffi"]
+
+            [lib]
+            crate-type = ["cdylib"]
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!(Some("MIT OR Apache-2.0".into()), toml.license);
+        assert_eq!(Some(PathBuf::from("LICENSE.txt")), toml.license_file);
+        assert_eq!(Some("https://example.com".into()), toml.homepage);
+        assert_eq!(Some(PathBuf::from("README.md")), toml.readme);
+        assert_eq!(vec!["native", "ffi"], toml.keywords);
+        assert_eq!(vec!["development-tools::ffi"], toml.categories);
+    }
+
+    #[test]
+    fn parse_toml_optional_repository_and_description() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version = "0.1.0"
+            authors = ["Somebody"]
+            description = ""
+
+            [lib]
+            crate-type = ["cdylib"]
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!(None, toml.repository);
+        assert_eq!(None, toml.description);
+    }
+
+    #[test]
+    fn parse_toml_repository_from_git() {
+        use std::fs::{self, File};
+        use std::io::Write;
+
+        let dir = test_dir!("parse-repository");
+
+        let files = [
+            (
+                ".git/config",
+                "[remote \"origin\"]\n\turl = https://github.com/KodrAus/cargo-nuget.git\n",
+            ),
+            (
+                "native/Cargo.toml",
+                r#"
+                    [package]
+                    name = "native"
+                    version = "0.1.0"
+                    authors = []
+
+                    [lib]
+                    crate-type = ["cdylib"]
+                "#,
+            ),
+        ];
+
+        for &(path, content) in &files {
+            let path = dir.join(path);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+        }
+
+        let git = ::git::repository(&dir.join("native"));
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromFile {
+                path: dir.join("native/Cargo.toml").to_string_lossy().into_owned().into(),
+            },
+            workspace: None,
+            git: git.as_ref(),
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!(Some("https://github.com/KodrAus/cargo-nuget.git".into()), toml.repository);
+    }
+
+    #[test]
+    fn parse_toml_nuget_paths_are_relative_to_manifest() {
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromFile {
+                path: "tests/native/Cargo.toml".into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!(Some(PathBuf::from("tests/native/icon.png")), toml.nuget.icon);
+    }
+
+    #[test]
+    fn parse_toml_lib_name() {
+        let parse = |toml: &str| {
+            let args = CargoParseArgs {
+                buf: CargoBufKind::FromBuf {
+                    buf: toml.as_bytes().to_vec().into(),
+                },
+                workspace: None,
+                git: None,
+            };
+
+            parse_toml(args).unwrap()
+        };
+
+        let toml = parse(
+            r#"
+                [package]
+                name = "my-native"
+                version = "0.1.0"
+                authors = ["Somebody"]
+                repository = ""
+                description = ""
+
+                [lib]
+                crate-type = ["cdylib"]
+            "#,
+        );
+
+        assert_eq!("my-native", toml.name);
+        assert_eq!("my_native", toml.lib_name);
+
+        let toml = parse(
+            r#"
+                [package]
+                name = "my-native"
+                version = "0.1.0"
+                authors = ["Somebody"]
+                repository = ""
+                description = ""
+
+                [lib]
+                name = "core_ffi"
+                crate-type = ["cdylib"]
+            "#,
+        );
+
+        assert_eq!("my-native", toml.name);
+        assert_eq!("core_ffi", toml.lib_name);
+    }
+
     #[test]
     fn parse_toml_from_file_is_valid() {
         let args = CargoParseArgs {
             buf: CargoBufKind::FromFile {
                 path: "tests/native/Cargo.toml".into(),
             },
+            workspace: None,
+            git: None,
         };
 
         let toml = parse_toml(args);
@@ -212,6 +1071,220 @@ mod tests {
         assert!(toml.is_ok());
     }
 
+    #[test]
+    fn parse_toml_from_workspace_is_valid() {
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromFile {
+                path: "tests/workspace/native_a/Cargo.toml".into(),
+            },
+            workspace: Some(CargoBufKind::FromFile {
+                path: "tests/workspace/Cargo.toml".into(),
+            }),
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!("0.0.1", toml.version);
+    }
+
+    #[test]
+    fn parse_toml_from_workspace_inherits_paths() {
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromFile {
+                path: "tests/workspace/native_a/Cargo.toml".into(),
+            },
+            workspace: Some(CargoBufKind::FromFile {
+                path: "tests/workspace/Cargo.toml".into(),
+            }),
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        let root = env::current_dir().unwrap().join("tests/workspace");
+
+        // Inherited paths are relative to the workspace root, not the package
+        assert_eq!(Some(root.join("README.md")), toml.readme);
+        assert_eq!(Some(root.join("LICENSE.txt")), toml.license_file);
+
+        assert!(toml.readme.unwrap().is_file());
+        assert!(toml.license_file.unwrap().is_file());
+    }
+
+    #[test]
+    fn parse_workspace_toml_skips_packages() {
+        let member = |name: &str| CargoParseArgs {
+            buf: CargoBufKind::FromFile {
+                path: format!("tests/workspace/{}/Cargo.toml", name).into(),
+            },
+            workspace: Some(CargoBufKind::FromFile {
+                path: "tests/workspace/Cargo.toml".into(),
+            }),
+            git: None,
+        };
+
+        let args = CargoParseWorkspaceArgs {
+            packages: vec![
+                member("native_a"),
+                member("native_b"),
+                member("native_skipped"),
+                member("common"),
+            ],
+        };
+
+        let names: Vec<_> = parse_workspace_toml(args)
+            .unwrap()
+            .into_iter()
+            .map(|config| config.name)
+            .collect();
+
+        assert_eq!(vec!["native_a", "native_b"], names);
+    }
+
+    #[test]
+    fn parse_toml_inherit_workspace() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version.workspace = true
+            authors = { workspace = true }
+            repository.workspace = true
+            description = ""
+
+            [lib]
+            crate-type = ["cdylib"]
+        "#;
+
+        let workspace = r#"
+            [workspace]
+            members = ["native"]
+
+            [workspace.package]
+            version = "0.1.0"
+            authors = ["Somebody"]
+            repository = "https://github.com/KodrAus/cargo-nuget"
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: Some(CargoBufKind::FromBuf {
+                buf: workspace.as_bytes().into(),
+            }),
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        let expected = CargoConfig {
+            name: "native".into(),
+            lib_name: "native".into(),
+            version: "0.1.0".into(),
+            authors: vec!["Somebody".into()],
+            repository: Some("https://github.com/KodrAus/cargo-nuget".into()),
+            description: None,
+            license: None,
+            license_file: None,
+            homepage: None,
+            readme: None,
+            keywords: vec![],
+            categories: vec![],
+            path_dependencies: vec![],
+            git: None,
+            nuget: CargoNugetConfig::default(),
+        };
+
+        assert_eq!(expected, toml);
+    }
+
+    #[test]
+    fn parse_toml_inherit_own_workspace() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version.workspace = true
+            authors = ["Somebody"]
+            repository = "https://github.com/KodrAus/cargo-nuget"
+            description = ""
+
+            [lib]
+            crate-type = ["cdylib"]
+
+            [workspace.package]
+            version = "0.2.0"
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        assert_eq!("0.2.0", toml.version);
+    }
+
+    #[test]
+    fn parse_toml_path_dependencies() {
+        let toml = r#"
+            [package]
+            name = "native"
+            version = "0.1.0"
+            authors = ["Somebody"]
+
+            [lib]
+            crate-type = ["cdylib"]
+
+            [dependencies]
+            serde = "1.0"
+            common = { path = "../common" }
+            interop = { path = "../interop", version = "~0.2" }
+            ffi = { workspace = true, optional = true }
+        "#;
+
+        let workspace = r#"
+            [workspace]
+            members = ["native"]
+
+            [workspace.dependencies]
+            ffi = { package = "native-ffi", path = "ffi", version = "1.0" }
+        "#;
+
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().into(),
+            },
+            workspace: Some(CargoBufKind::FromBuf {
+                buf: workspace.as_bytes().into(),
+            }),
+            git: None,
+        };
+
+        let toml = parse_toml(args).unwrap();
+
+        let expected = vec![
+            CargoPathDependency {
+                name: "common".into(),
+                version: None,
+            },
+            CargoPathDependency {
+                name: "native-ffi".into(),
+                version: Some("1.0".into()),
+            },
+            CargoPathDependency {
+                name: "interop".into(),
+                version: Some("~0.2".into()),
+            },
+        ];
+
+        assert_eq!(expected, toml.path_dependencies);
+    }
+
     #[test]
     fn parse_toml_cdylib_is_valid() {
         let toml = r#"
@@ -230,6 +1303,8 @@ mod tests {
             buf: CargoBufKind::FromBuf {
                 buf: toml.as_bytes().into(),
             },
+            workspace: None,
+            git: None,
         };
 
         let toml = parse_toml(args);
@@ -240,7 +1315,9 @@ mod tests {
     macro_rules! assert_inavlid {
         ($input:expr, $err:pat) => ({
             let args = CargoParseArgs {
-                buf: CargoBufKind::FromBuf { buf: $input.as_bytes().into() }
+                buf: CargoBufKind::FromBuf { buf: $input.as_bytes().into() },
+                workspace: None,
+                git: None,
             };
 
             let toml = parse_toml(args);
@@ -252,6 +1329,14 @@ mod tests {
         })
     }
 
+    fn toml_args(toml: &str) -> CargoParseArgs<'_> {
+        CargoParseArgs {
+            buf: CargoBufKind::FromBuf { buf: toml.as_bytes().into() },
+            workspace: None,
+            git: None,
+        }
+    }
+
     #[test]
     fn parse_toml_missing_version() {
         assert_inavlid!(
@@ -282,6 +1367,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_toml_missing_workspace_key() {
+        assert_inavlid!(
+            r#"
+                [package]
+                name = "native"
+                version.workspace = true
+                authors = ["Somebody", "Somebody Else"]
+
+                [lib]
+                crate-type = ["rlib", "dylib"]
+            "#,
+            CargoParseError::Key(CargoKeyError::MissingWorkspace { .. })
+        );
+    }
+
+    #[test]
+    fn parse_toml_nuget_invalid_version_strategy() {
+        assert_inavlid!(
+            r#"
+                [package]
+                name = "native"
+                version = "0.1.0"
+                authors = ["Somebody"]
+                repository = ""
+                description = ""
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.nuget]
+                version-strategy = "carrots"
+            "#,
+            CargoParseError::Key(CargoKeyError::Invalid { .. })
+        );
+    }
+
+    #[test]
+    fn parse_toml_invalid_nuget_dependency() {
+        let cases = vec![
+            (r#""Native.Managed" = { verison = "1.0.0" }"#, "dependencies.Native.Managed.version"),
+            (r#""Native.Managed" = 1"#, "dependencies.Native.Managed"),
+            (
+                r#""Native.Managed" = { version = "1.0.0", target-framework = 2 }"#,
+                "dependencies.Native.Managed.target-framework",
+            ),
+        ];
+
+        for (dependency, expected) in cases {
+            let toml = format!(
+                r#"
+                    [package]
+                    name = "native"
+                    version = "0.1.0"
+                    authors = ["Somebody"]
+
+                    [lib]
+                    crate-type = ["cdylib"]
+
+                    [package.metadata.nuget.dependencies]
+                    {}
+                "#,
+                dependency
+            );
+
+            match parse_toml(toml_args(&toml)) {
+                Err(CargoParseError::Key(CargoKeyError::Invalid { ref key, .. })) => {
+                    assert_eq!(expected, key)
+                }
+                r => panic!("{:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_toml_invalid_nuget_file() {
+        let cases = vec![
+            (r#"files = ["README.md", { source = "docs/guide.md" }]"#, "files[1].src"),
+            (r#"files = [{ src = "docs/guide.md", target = 1 }]"#, "files[0].target"),
+            (r#"files = [1]"#, "files[0]"),
+        ];
+
+        for (files, expected) in cases {
+            let toml = format!(
+                r#"
+                    [package]
+                    name = "native"
+                    version = "0.1.0"
+                    authors = ["Somebody"]
+
+                    [lib]
+                    crate-type = ["cdylib"]
+
+                    [package.metadata.nuget]
+                    {}
+                "#,
+                files
+            );
+
+            match parse_toml(toml_args(&toml)) {
+                Err(CargoParseError::Key(CargoKeyError::Invalid { ref key, .. })) => {
+                    assert_eq!(expected, key)
+                }
+                r => panic!("{:?}", r),
+            }
+        }
+    }
+
     #[test]
     fn parse_toml_not_a_dylib() {
         assert_inavlid!(
@@ -1,31 +1,170 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
 use chrono::UTC;
 use semver::{Identifier, SemVerError, Version};
 
-/// Args for adding a dev tag to a semver version.
+use git;
+
+/// Environment variables that CI services put a build number in.
+const CI_BUILD_NUMBER_VARS: &[&str] = &[
+    "BUILD_NUMBER",
+    "GITHUB_RUN_NUMBER",
+    "BUILD_BUILDID",
+    "CI_PIPELINE_IID",
+    "APPVEYOR_BUILD_NUMBER",
+    "TRAVIS_BUILD_NUMBER",
+    "CIRCLE_BUILD_NUM",
+];
+
+/// Args for adding a pre-release tag to a semver version.
 #[derive(Debug, PartialEq)]
 pub struct CargoLocalVersionArgs<'a> {
     pub version: &'a str,
+    /// A version to use as-is instead of generating one.
+    pub explicit: Option<Cow<'a, str>>,
+    /// Take the version from a `v*` git tag on `HEAD` instead of generating one.
+    pub git_tag: Option<CargoGitTagVersion>,
+    pub strategy: CargoVersionStrategy,
+    /// The pre-release label to add, like `dev`.
+    pub label: Cow<'a, str>,
+    /// The directory of the crate, used to read git history.
+    pub work_dir: Cow<'a, Path>,
+    /// The file the build counter is kept in.
+    pub counter_path: Cow<'a, Path>,
+}
+
+/// The way a version is generated for a build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CargoVersionStrategy {
+    /// Add a pre-release tag with the current unix timestamp.
+    Timestamp,
+    /// Add a pre-release tag with a counter that's incremented for each build.
+    Counter,
+    /// Add a pre-release tag with the number of commits, and the commit hash as build metadata.
+    Git,
+    /// Add a pre-release tag with the build number from a CI service.
+    Ci,
+    /// Use the version in `Cargo.toml` as-is.
+    Manifest,
 }
 
-/// A version with a dev tag added.
+impl CargoVersionStrategy {
+    /// Get a strategy from its name, like `timestamp`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timestamp" => Some(CargoVersionStrategy::Timestamp),
+            "counter" => Some(CargoVersionStrategy::Counter),
+            "git" => Some(CargoVersionStrategy::Git),
+            "ci" => Some(CargoVersionStrategy::Ci),
+            "manifest" => Some(CargoVersionStrategy::Manifest),
+            _ => None,
+        }
+    }
+}
+
+/// How a version taken from a git tag is checked against the crate version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CargoGitTagVersion {
+    /// The tag must match the version in `Cargo.toml`.
+    Match,
+    /// The tag is used even if it doesn't match the version in `Cargo.toml`.
+    Override,
+}
+
+/// A version with a pre-release tag added.
 #[derive(Debug, PartialEq)]
 pub struct CargoLocalVersion {
     pub version: String,
 }
 
+/// Generate a version for the build using the given strategy.
+///
+/// An explicit version is always used instead of generating one.
 pub fn local_version_tag<'a>(
-    ver: CargoLocalVersionArgs<'a>,
+    args: CargoLocalVersionArgs<'a>,
 ) -> Result<CargoLocalVersion, CargoLocalVersionError> {
-    let mut ver = Version::parse(ver.version)?;
-    let build = UTC::now().timestamp();
+    // Explicit versions are checked when they're packed, so they can use 4 parts
+    if let Some(explicit) = args.explicit {
+        return Ok(CargoLocalVersion {
+            version: explicit.into_owned(),
+        });
+    }
 
-    if build < 0 {
-        Err(CargoLocalVersionError::PreEpoch)?;
+    let mut ver = Version::parse(args.version)?;
+
+    if let Some(git_tag) = args.git_tag {
+        // Only a tag on `HEAD` is used, so a later untagged commit isn't packed as a release
+        let tag = git(&args.work_dir, &["describe", "--tags", "--exact-match", "--match", "v*"])
+            .map_err(|_| CargoLocalVersionError::MissingTag)?;
+
+        let tag_ver = Version::parse(&tag["v".len()..])
+            .map_err(|_| CargoLocalVersionError::InvalidTag { tag: tag.clone() })?;
+
+        match (git_tag, tag_ver == ver) {
+            (CargoGitTagVersion::Match, false) => Err(CargoLocalVersionError::TagMismatch {
+                tag,
+                version: ver.to_string(),
+            })?,
+            (CargoGitTagVersion::Override, false) => {
+                warn!("using version '{}' from tag '{}' instead of '{}'", tag_ver, tag, ver)
+            }
+            (_, true) => (),
+        }
+
+        return Ok(CargoLocalVersion {
+            version: tag_ver.to_string(),
+        });
     }
 
-    let build = build as u64;
+    let label = &args.label;
 
-    add_pretag(&mut ver, "dev", build);
+    match args.strategy {
+        CargoVersionStrategy::Timestamp => {
+            let build = UTC::now().timestamp();
+
+            if build < 0 {
+                Err(CargoLocalVersionError::PreEpoch)?;
+            }
+
+            add_pretag(&mut ver, label, build as u64);
+        }
+        CargoVersionStrategy::Counter => {
+            let build = increment_counter(&args.counter_path)?;
+
+            add_pretag(&mut ver, label, build);
+        }
+        CargoVersionStrategy::Git => {
+            let commit = git::head_commit(&args.work_dir).ok_or_else(|| {
+                CargoLocalVersionError::MissingCommit {
+                    dir: args.work_dir.to_string_lossy().into_owned(),
+                }
+            })?;
+
+            let count = git(&args.work_dir, &["rev-list", "--count", "HEAD"])?;
+            let count = count.parse().map_err(|_| {
+                CargoLocalVersionError::Git {
+                    command: "rev-list --count HEAD".into(),
+                }
+            })?;
+
+            add_pretag(&mut ver, label, count);
+            ver.build = vec![Identifier::AlphaNumeric(git::short_hash(&commit).into())];
+        }
+        CargoVersionStrategy::Ci => {
+            let build = ci_build_number(|var| env::var(var).ok())
+                .ok_or(CargoLocalVersionError::MissingBuildNumber)?;
+
+            add_pretag(&mut ver, label, build);
+        }
+        CargoVersionStrategy::Manifest => (),
+    }
 
     Ok(CargoLocalVersion {
         version: ver.to_string(),
@@ -33,7 +172,7 @@ pub fn local_version_tag<'a>(
 }
 
 fn add_pretag(ver: &mut Version, tag: &str, num: u64) {
-    if ver.pre.len() == 0 {
+    if ver.pre.is_empty() {
         ver.pre.push(Identifier::AlphaNumeric(tag.into()));
     }
 
@@ -42,6 +181,153 @@ fn add_pretag(ver: &mut Version, tag: &str, num: u64) {
     ver.build = vec![];
 }
 
+/// How long to wait for another build to finish with the counter.
+const COUNTER_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Increment the build counter in the given file and return it.
+///
+/// The counter starts at `1` if the file doesn't exist yet.
+/// A `.lock` file next to the counter is held while it's updated, so concurrent builds
+/// don't get the same number, and the new value is renamed over the old one so a build
+/// that stops part way through doesn't leave a partly written counter.
+fn increment_counter(path: &Path) -> Result<u64, CargoLocalVersionError> {
+    let counter_err = |err| {
+        CargoLocalVersionError::Counter {
+            path: path.to_string_lossy().into_owned(),
+            err,
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(&counter_err)?;
+    }
+
+    let _lock = CounterLock::acquire(path).map_err(&counter_err)?;
+
+    let mut counter = String::new();
+
+    if path.exists() {
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut counter))
+            .map_err(&counter_err)?;
+    }
+
+    let counter = match counter.trim() {
+        "" => 1,
+        counter => counter
+            .parse::<u64>()
+            .ok()
+            .and_then(|counter| counter.checked_add(1))
+            .ok_or_else(|| {
+                let msg = format!(
+                    "'{}' isn't a build number\nFix or remove the file to continue",
+                    counter
+                );
+
+                counter_err(IoError::new(IoErrorKind::InvalidData, msg))
+            })?,
+    };
+
+    let tmp = sibling(path, "tmp");
+
+    File::create(&tmp)
+        .and_then(|mut f| write!(f, "{}", counter).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(&counter_err)?;
+
+    Ok(counter)
+}
+
+/// A lock on the build counter, that's released when it's dropped.
+struct CounterLock {
+    path: PathBuf,
+}
+
+impl CounterLock {
+    /// Create the lock file, waiting for any other build that holds it.
+    fn acquire(counter: &Path) -> Result<Self, IoError> {
+        let path = sibling(counter, "lock");
+        let start = Instant::now();
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(CounterLock { path }),
+                Err(ref err) if err.kind() == IoErrorKind::AlreadyExists => {
+                    if start.elapsed() > COUNTER_LOCK_TIMEOUT {
+                        let msg = format!(
+                            "the counter is locked by '{}'\nRemove it if no other build is running",
+                            path.display()
+                        );
+
+                        return Err(IoError::new(IoErrorKind::TimedOut, msg));
+                    }
+
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for CounterLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Get a path next to the counter with an extra extension, like `version-counter.lock`.
+fn sibling(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ext);
+
+    path.with_file_name(name)
+}
+
+/// Run a local `git` command and return its trimmed output.
+///
+/// The `HEAD` commit is read from the `.git` directory, but counting commits and finding
+/// tags means walking the history through compressed and packed objects, so that's left
+/// to `git`.
+fn git(work_dir: &Path, args: &[&str]) -> Result<String, CargoLocalVersionError> {
+    let command = args.join(" ");
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(work_dir)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| {
+            CargoLocalVersionError::GitIo {
+                command: command.clone(),
+                err,
+            }
+        })?;
+
+    let output = if output.status.success() {
+        str::from_utf8(&output.stdout).ok()
+    } else {
+        None
+    };
+
+    output
+        .map(|output| output.trim().to_owned())
+        .ok_or(CargoLocalVersionError::Git { command })
+}
+
+/// Get the build number from the first known CI environment variable that's set.
+fn ci_build_number<F>(var: F) -> Option<u64>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    CI_BUILD_NUMBER_VARS
+        .iter()
+        .filter_map(|name| var(name))
+        .filter_map(|build| build.trim().parse().ok())
+        .next()
+}
+
 quick_error!{
 /// An error encountered while updating a semver version.
     #[derive(Debug)]
@@ -54,14 +340,283 @@ quick_error!{
         PreEpoch {
             display("Current timestamp is before the epoch\nYou are either a time traveller or there's an error with your clock")
         }
+        Counter { path: String, err: IoError } {
+            cause(err)
+            display("Error updating the build counter at '{}'\nCaused by: {}", path, err)
+        }
+        Git { command: String } {
+            display("Error running `git {}`\nOutput (if any) should be written to stderr", command)
+        }
+        GitIo { command: String, err: IoError } {
+            cause(err)
+            display("Error running `git {}`\nCheck `git` is installed and on the PATH\nCaused by: {}", command, err)
+        }
+        MissingCommit { dir: String } {
+            display("No git commit was found for the crate in '{}'\nThe `git` version strategy needs the crate to be in a git repository with at least one commit", dir)
+        }
+        MissingTag {
+            display("No `v*` git tag was found on the current commit to take the version from\nOutput (if any) should be written to stderr")
+        }
+        InvalidTag { tag: String } {
+            display("The git tag '{}' isn't a `v` followed by a valid version, like `v1.0.0`", tag)
+        }
+        TagMismatch { tag: String, version: String } {
+            display("The git tag '{}' doesn't match the crate version '{}'\nUpdate the crate version or pass `--version-override` to use the tag", tag, version)
+        }
+        MissingBuildNumber {
+            display("No CI build number was found\nThe build number is read from any of: {}", CI_BUILD_NUMBER_VARS.join(", "))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
     use super::*;
     use semver::Version;
 
+    fn version_args(strategy: CargoVersionStrategy) -> CargoLocalVersionArgs<'static> {
+        let work_dir: &Path = ".".as_ref();
+        let counter_path: &Path = "target/version-counter".as_ref();
+
+        CargoLocalVersionArgs {
+            version: "0.0.1",
+            explicit: None,
+            git_tag: None,
+            strategy,
+            label: "dev".into(),
+            work_dir: work_dir.into(),
+            counter_path: counter_path.into(),
+        }
+    }
+
+    #[test]
+    fn manifest_strategy_keeps_version() {
+        let args = CargoLocalVersionArgs {
+            version: "0.0.1-carrots",
+            ..version_args(CargoVersionStrategy::Manifest)
+        };
+
+        let ver = local_version_tag(args).unwrap();
+
+        assert_eq!("0.0.1-carrots", &ver.version);
+    }
+
+    #[test]
+    fn explicit_version_replaces_strategy() {
+        let args = CargoLocalVersionArgs {
+            explicit: Some("1.2.3-beta.1".into()),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        let ver = local_version_tag(args).unwrap();
+
+        assert_eq!("1.2.3-beta.1", &ver.version);
+    }
+
+    #[test]
+    fn timestamp_strategy_uses_label() {
+        let args = CargoLocalVersionArgs {
+            label: "nightly".into(),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        let ver = local_version_tag(args).unwrap();
+
+        assert!(ver.version.starts_with("0.0.1-nightly."));
+    }
+
+    #[test]
+    fn counter_strategy_increments() {
+        let path = test_dir!("counter").join("version-counter");
+
+        let args = || {
+            CargoLocalVersionArgs {
+                counter_path: path.clone().into(),
+                ..version_args(CargoVersionStrategy::Counter)
+            }
+        };
+
+        assert_eq!("0.0.1-dev.1", local_version_tag(args()).unwrap().version);
+        assert_eq!("0.0.1-dev.2", local_version_tag(args()).unwrap().version);
+    }
+
+    #[test]
+    fn counter_strategy_concurrent() {
+        let path = test_dir!("counter-concurrent").join("version-counter");
+
+        let builds: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+
+                thread::spawn(move || increment_counter(&path).unwrap())
+            })
+            .collect();
+
+        let mut counters: Vec<_> = builds.into_iter().map(|build| build.join().unwrap()).collect();
+        counters.sort();
+
+        // Every build gets its own number
+        assert_eq!((1..9).collect::<Vec<_>>(), counters);
+        assert!(!sibling(&path, "lock").exists());
+    }
+
+    #[test]
+    fn counter_strategy_invalid() {
+        let path = test_dir!("counter-invalid").join("version-counter");
+
+        fs::write(&path, "4\n2").unwrap();
+
+        match increment_counter(&path) {
+            Err(CargoLocalVersionError::Counter { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+
+        // The counter isn't reset
+        assert_eq!("4\n2", fs::read_to_string(&path).unwrap());
+    }
+
+    /// Create a git repository in the temp directory with a single tagged commit.
+    fn tagged_repo(name: &str, tag: &str) -> PathBuf {
+        let dir = test_dir!(name);
+
+        let commands: &[&[&str]] = &[
+            &["init", "-q"],
+            &["config", "user.name", "test"],
+            &["config", "user.email", "test"],
+            &["config", "commit.gpgsign", "false"],
+            &["commit", "-qm", "init", "--allow-empty"],
+            &["tag", tag],
+        ];
+
+        for args in commands {
+            git(&dir, args).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn git_tag_matches_version() {
+        let repo = tagged_repo("git-tag-match", "v0.0.1");
+
+        let args = CargoLocalVersionArgs {
+            git_tag: Some(CargoGitTagVersion::Match),
+            work_dir: repo.into(),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        let ver = local_version_tag(args).unwrap();
+
+        assert_eq!("0.0.1", &ver.version);
+    }
+
+    #[test]
+    fn git_tag_mismatch() {
+        let repo = tagged_repo("git-tag-mismatch", "v0.1.0");
+
+        let args = CargoLocalVersionArgs {
+            git_tag: Some(CargoGitTagVersion::Match),
+            work_dir: repo.into(),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        match local_version_tag(args) {
+            Err(CargoLocalVersionError::TagMismatch { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn git_tag_overrides_version() {
+        let repo = tagged_repo("git-tag-override", "v0.1.0-rc.1");
+
+        let args = CargoLocalVersionArgs {
+            git_tag: Some(CargoGitTagVersion::Override),
+            work_dir: repo.into(),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        let ver = local_version_tag(args).unwrap();
+
+        assert_eq!("0.1.0-rc.1", &ver.version);
+    }
+
+    #[test]
+    fn git_tag_not_on_head() {
+        let repo = tagged_repo("git-tag-not-on-head", "v0.0.1");
+        git(&repo, &["commit", "-qm", "next", "--allow-empty"]).unwrap();
+
+        let args = CargoLocalVersionArgs {
+            git_tag: Some(CargoGitTagVersion::Match),
+            work_dir: repo.into(),
+            ..version_args(CargoVersionStrategy::Timestamp)
+        };
+
+        match local_version_tag(args) {
+            Err(CargoLocalVersionError::MissingTag) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn git_tag_invalid() {
+        for tag in &["v", "vNext"] {
+            let repo = tagged_repo(&format!("git-tag-invalid-{}", tag), tag);
+
+            let args = CargoLocalVersionArgs {
+                git_tag: Some(CargoGitTagVersion::Override),
+                work_dir: repo.into(),
+                ..version_args(CargoVersionStrategy::Timestamp)
+            };
+
+            match local_version_tag(args) {
+                Err(CargoLocalVersionError::InvalidTag { ref tag }) if tag.starts_with('v') => (),
+                r => panic!("{:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn git_strategy_adds_count_and_hash() {
+        let ver = local_version_tag(version_args(CargoVersionStrategy::Git)).unwrap();
+
+        let ver = Version::parse(&ver.version).unwrap();
+
+        let commit = git::head_commit(".".as_ref()).unwrap();
+
+        assert_eq!(Identifier::AlphaNumeric("dev".into()), ver.pre[0]);
+        assert_eq!(vec![Identifier::AlphaNumeric(commit[..7].into())], ver.build);
+    }
+
+    #[test]
+    fn git_strategy_without_commits() {
+        let repo = test_dir!("git-no-commits");
+        git(&repo, &["init", "-q"]).unwrap();
+
+        let args = CargoLocalVersionArgs {
+            work_dir: repo.into(),
+            ..version_args(CargoVersionStrategy::Git)
+        };
+
+        match local_version_tag(args) {
+            Err(CargoLocalVersionError::MissingCommit { .. }) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn ci_build_number_from_env() {
+        let build = ci_build_number(|var| match var {
+            "GITHUB_RUN_NUMBER" => Some("42".into()),
+            "TRAVIS_BUILD_NUMBER" => Some("7".into()),
+            _ => None,
+        });
+
+        assert_eq!(Some(42), build);
+        assert_eq!(None, ci_build_number(|_| None));
+    }
+
     #[test]
     fn add_pretag_and_build() {
         let mut ver = Version::parse("0.0.1").unwrap();
@@ -2,15 +2,26 @@ use std::error::Error;
 use clap::ArgMatches;
 
 use {cargo, nuget};
+use cargo::CargoVersionStrategy;
 
-pub fn call(args: &ArgMatches) -> Result<(), Box<Error>> {
-    let cargo_toml = pass!("reading cargo manifest" => args => cargo::parse_toml);
+pub fn call(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let cargo_meta = pass!("reading cargo metadata" => args => cargo::metadata);
 
-    let cargo_libs = pass!("building Rust lib" => (args, &cargo_toml) => cargo::build_cross);
+    let mut cargo_toml = pass!("reading cargo manifest" => &cargo_meta => cargo::parse_toml);
+
+    let version = pass!("generating package version" => (args, &cargo_meta, &cargo_toml, CargoVersionStrategy::Manifest) => cargo::local_version_tag);
+
+    cargo_toml.version = version.version;
+
+    let cargo_libs = pass!("building Rust lib" => (args, &cargo_meta, &cargo_toml) => cargo::build_cross);
 
     let nuspec = pass!("building nuspec" => &cargo_toml => nuget::spec);
 
-    let nupkg = pass!("building nupkg" => (&nuspec, &cargo_libs) => nuget::pack);
+    let nupkg = pass!("building nupkg" => (&cargo_toml, &nuspec, &cargo_libs) => nuget::pack);
+
+    for rid in &nupkg.uncovered {
+        warn!("the nupkg doesn't include a native library that '{}' consumers can use", rid);
+    }
 
     pass!("saving nupkg" => (args, &nupkg) => nuget::save_nupkg);
 
@@ -46,3 +46,22 @@ macro_rules! assert_eq_no_ws {
         }
     })
 }
+
+/// Get an empty temp directory for a test.
+///
+/// The directory is unique to the test module, name and process, so tests can run concurrently.
+#[cfg(test)]
+#[macro_export]
+macro_rules! test_dir {
+    ($name:expr) => ({
+        use std::{env, fs, process};
+
+        let module = module_path!().replace("::", "-");
+        let dir = env::temp_dir().join(format!("{}-{}-{}", module, $name, process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    })
+}
@@ -10,6 +10,7 @@ extern crate log;
 #[macro_use]
 extern crate quick_error;
 extern crate semver;
+extern crate serde_json;
 extern crate term_painter;
 extern crate toml;
 extern crate xml;
@@ -23,12 +24,13 @@ pub mod nuget;
 pub mod pack;
 pub mod cross;
 mod args;
+mod git;
 mod logger;
 
 use std::error::Error;
 use std::process;
 
-fn get_command(args: &clap::ArgMatches) -> Option<Result<(), Box<Error>>> {
+fn get_command(args: &clap::ArgMatches) -> Option<Result<(), Box<dyn Error>>> {
     // Run pack command
     let pack_cmd = || args.subcommand_matches(args::PACK_CMD).map(pack::call);
 
@@ -43,14 +45,17 @@ fn main() {
 
     let args = args::app().get_matches();
 
+    if let Some(cross) = args.subcommand_matches(args::CROSS_CMD) {
+        args::validate_targets(cross).unwrap_or_else(|e| e.exit());
+    }
+
     let mut result = BuildResult::default();
 
     if let Some(cmd) = get_command(&args) {
         result.ran = true;
 
-        match cmd {
-            Err(e) => result.err = Some(e),
-            _ => (),
+        if let Err(e) = cmd {
+            result.err = Some(e);
         }
     }
 
@@ -58,7 +63,7 @@ fn main() {
         BuildResult { ran: false, .. } => {
             // print help and exit
             args::app().print_help().unwrap();
-            println!("");
+            println!();
         }
         BuildResult { err: Some(e), .. } => {
             // print error and exit
@@ -80,5 +85,5 @@ fn main() {
 #[derive(Default)]
 struct BuildResult {
     ran: bool,
-    err: Option<Box<Error>>,
+    err: Option<Box<dyn Error>>,
 }
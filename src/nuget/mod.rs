@@ -3,21 +3,25 @@
 mod spec;
 mod pack;
 mod save;
+mod version;
+pub mod runtime;
 
 mod util;
 
 pub use self::spec::*;
 pub use self::pack::*;
 pub use self::save::*;
+pub use self::version::*;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fmt::{Debug, Error as FmtError, Formatter};
 use std::collections::HashMap;
 use std::borrow::Cow;
 use std::ops::Deref;
 use clap::ArgMatches;
+use semver::Version;
 
-use cargo::{CargoBuildOutput, CargoConfig};
+use cargo::{CargoBuildOutput, CargoConfig, CargoPathDependency};
 use args::NUPKG_DIR_ARG;
 
 /// A wrapper around an owned byte buffer.
@@ -50,34 +54,219 @@ impl Debug for Buf {
 /// Build args to format a nuspec from cargo toml.
 impl<'a> From<&'a CargoConfig> for NugetSpecArgs<'a> {
     fn from(cargo: &'a CargoConfig) -> Self {
+        let nuget = &cargo.nuget;
+
+        let join = |vals: &[String], sep| match vals.len() {
+            0 => None,
+            _ => Some(Cow::Owned(vals.join(sep))),
+        };
+
+        let file_name = |path: &'a Path| path.file_name().map(|name| name.to_string_lossy());
+
+        let authors = cargo.authors.join(", ");
+
+        // Tags default to the crate keywords and categories
+        // Only the last part of a category like `development-tools::ffi` is used
+        let tags = match nuget.tags.len() {
+            0 => {
+                let mut tags = cargo.keywords.clone();
+
+                for category in &cargo.categories {
+                    let tag = category.rsplit("::").next().unwrap_or(category).to_owned();
+
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+
+                tags
+            }
+            _ => nuget.tags.clone(),
+        };
+
+        // The license expression in nuget config takes precedence over the crate license
+        let license = nuget.license.as_ref().or(cargo.license.as_ref());
+
+        let license = match (license, cargo.license_file.as_ref()) {
+            (Some(license), _) => Some(NugetLicense::Expression(Cow::Borrowed(license))),
+            (None, Some(license_file)) => file_name(license_file).map(NugetLicense::File),
+            (None, None) => None,
+        };
+
+        // The repository url in the manifest is used with the state of the local git repository
+        let repository = match (cargo.repository.as_ref(), cargo.git.as_ref()) {
+            (None, None) => None,
+            (url, git) => Some(NugetRepository {
+                kind: git.map(|_| Cow::Borrowed("git")),
+                url: url.map(|url| Cow::Borrowed(url.as_ref())),
+                branch: git.and_then(|git| git.branch.as_ref())
+                    .map(|branch| Cow::Borrowed(branch.as_ref())),
+                commit: git.and_then(|git| git.commit.as_ref())
+                    .map(|commit| Cow::Borrowed(commit.as_ref())),
+            }),
+        };
+
+        let copyright = match (nuget.copyright.as_ref(), authors.len()) {
+            (Some(copyright), _) => Some(Cow::Borrowed(copyright.as_ref())),
+            (None, 0) => None,
+            (None, _) => Some(Cow::Owned(format!("Copyright {}", authors))),
+        };
+
+        let mut dependencies = match nuget.platforms_dependency.unwrap_or(true) {
+            true => NugetDependencies::default(),
+            false => NugetDependencies::empty(),
+        };
+
+        dependencies.extend(nuget.dependencies.iter().map(|dependency| {
+            NugetDependency {
+                id: Cow::Borrowed(&dependency.id),
+                version: Cow::Borrowed(&dependency.version),
+                target_framework: dependency
+                    .target_framework
+                    .as_ref()
+                    .map(|framework| Cow::Borrowed(framework.as_ref())),
+            }
+        }));
+
         NugetSpecArgs {
-            id: Cow::Borrowed(&cargo.name),
+            id: Cow::Borrowed(nuget.id.as_ref().unwrap_or(&cargo.name)),
             version: Cow::Borrowed(&cargo.version),
-            authors: Cow::Owned((&cargo.authors).join(", ")),
-            description: Cow::Borrowed(&cargo.description),
-            repository: Cow::Borrowed(&cargo.repository),
-            dependencies: NugetDependencies::default(),
+            title: Some(Cow::Borrowed(nuget.title.as_ref().unwrap_or(&cargo.name))),
+            authors: Cow::Owned(authors),
+            owners: join(&nuget.owners, ", "),
+            description: cargo.description.as_ref().map(|desc| Cow::Borrowed(desc.as_ref())),
+            repository,
+            project_url: cargo.homepage.as_ref().map(|homepage| Cow::Borrowed(homepage.as_ref())),
+            copyright,
+            tags: join(&tags, " "),
+            icon: nuget.icon.as_ref().and_then(|icon| file_name(icon)),
+            readme: cargo.readme.as_ref().and_then(|readme| file_name(readme)),
+            license,
+            dependencies,
         }
     }
 }
 
-/// Build args to pack a nupkg from nuspec and cargo build.
-impl<'a, I> From<(&'a Nuspec<'a>, I)> for NugetPackArgs<'a>
+/// Build args to format a nuspec for a package that's packed with others in a workspace.
+///
+/// Path dependencies on the other packages become NuGet dependencies on their packages.
+impl<'a> From<(&'a CargoConfig, &'a [CargoConfig])> for NugetSpecArgs<'a> {
+    fn from((cargo, packages): (&'a CargoConfig, &'a [CargoConfig])) -> Self {
+        let mut args = NugetSpecArgs::from(cargo);
+
+        let dependencies = cargo.path_dependencies.iter().filter_map(|dependency| {
+            let package = packages.iter().find(|package| package.name == dependency.name)?;
+
+            Some(NugetDependency {
+                id: Cow::Borrowed(package.nuget.id.as_ref().unwrap_or(&package.name)),
+                version: Cow::Owned(workspace_range(dependency, package)),
+                target_framework: None,
+            })
+        });
+
+        args.dependencies.extend(dependencies);
+
+        args
+    }
+}
+
+/// Get the NuGet version range for a path dependency on a package being packed.
+///
+/// The Cargo version requirement is used if there is one, otherwise any version from the one
+/// being packed is allowed. Generated pre-release versions, like `1.0.0-dev.1`, sort before the
+/// requirement, so the version being packed is always the smallest one allowed.
+fn workspace_range(dependency: &CargoPathDependency, package: &CargoConfig) -> String {
+    let version = match Version::parse(&package.version) {
+        Ok(version) => version,
+        Err(_) => return format!("[{}, )", package.version),
+    };
+
+    let packed = NugetVersionBound {
+        version,
+        inclusive: true,
+    };
+
+    let range = dependency.version.as_ref().and_then(|req| {
+        NugetVersionRange::from_cargo_req(req)
+            .map_err(|e| warn!("using the packed version of '{}' because {}", package.name, e))
+            .ok()
+    });
+
+    let range = match range {
+        Some(mut range) => {
+            let below_min = match range.min {
+                Some(ref min) => packed.version < min.version,
+                None => false,
+            };
+
+            if packed.version.is_prerelease() && below_min {
+                range.min = Some(packed);
+            }
+
+            range
+        }
+        None => NugetVersionRange {
+            min: Some(packed),
+            max: None,
+        },
+    };
+
+    range.to_string()
+}
+
+/// Build args to pack a nupkg from toml config, nuspec and cargo build.
+impl<'a, I> From<(&'a CargoConfig, &'a Nuspec<'a>, I)> for NugetPackArgs<'a>
 where
     I: IntoIterator<Item = &'a CargoBuildOutput>,
 {
-    fn from((nuspec, builds): (&'a Nuspec, I)) -> Self {
+    fn from((cargo, nuspec, builds): (&'a CargoConfig, &'a Nuspec, I)) -> Self {
         let mut libs = HashMap::new();
+        let mut rustc_versions = HashMap::new();
 
         for build in builds {
-            libs.insert(build.target, Cow::Borrowed(build.path.as_ref()));
+            libs.insert(build.target.clone(), Cow::Borrowed(build.path.as_ref()));
+
+            if let Some(ref version) = build.rustc_version {
+                rustc_versions.insert(build.target.clone(), Cow::Borrowed(version.as_ref()));
+            }
+        }
+
+        let mut files: Vec<_> = cargo
+            .nuget
+            .files
+            .iter()
+            .map(|file| {
+                NugetFile {
+                    src: Cow::Borrowed(file.src.as_ref()),
+                    target: Cow::Borrowed(&file.target),
+                }
+            })
+            .collect();
+
+        // A license file is only used if there's no license expression
+        let license_file = match (cargo.nuget.license.as_ref(), cargo.license.as_ref()) {
+            (None, None) => cargo.license_file.as_ref(),
+            _ => None,
+        };
+
+        // The icon, readme and license file are referenced from the root of the package
+        let root_files = vec![cargo.nuget.icon.as_ref(), cargo.readme.as_ref(), license_file];
+
+        for path in root_files.into_iter().flatten() {
+            files.push(NugetFile {
+                src: Cow::Borrowed(path.as_ref()),
+                target: "".into(),
+            });
         }
 
         NugetPackArgs {
             id: Cow::Borrowed(&nuspec.id),
-            version: Cow::Borrowed(&nuspec.version),
+            version: Cow::Owned(nuspec.version.normalized()),
+            lib_name: Cow::Borrowed(&cargo.lib_name),
             spec: &nuspec.xml,
             cargo_libs: libs,
+            rustc_versions,
+            files,
         }
     }
 }
@@ -98,3 +287,191 @@ impl<'a> From<(&'a ArgMatches<'a>, &'a Nupkg<'a>)> for NugetSaveArgs<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cargo::{parse_toml, CargoBufKind, CargoParseArgs};
+    use super::*;
+
+    fn cargo_config(toml: &str) -> CargoConfig {
+        let args = CargoParseArgs {
+            buf: CargoBufKind::FromBuf {
+                buf: toml.as_bytes().to_vec().into(),
+            },
+            workspace: None,
+            git: None,
+        };
+
+        parse_toml(args).unwrap()
+    }
+
+    #[test]
+    fn spec_args_from_cargo_metadata() {
+        let cargo = cargo_config(
+            r#"
+                [package]
+                name = "native"
+                version = "0.1.0"
+                authors = ["Somebody", "Somebody Else"]
+                repository = ""
+                description = ""
+                license-file = "LICENSE.txt"
+                homepage = "https://example.com"
+                readme = "docs/README.md"
+                keywords = ["native", "ffi"]
+                categories = ["development-tools::ffi", "os"]
+
+                [lib]
+                crate-type = ["cdylib"]
+            "#,
+        );
+
+        let args = NugetSpecArgs::from(&cargo);
+
+        assert_eq!(Some(Cow::Borrowed("native")), args.title);
+        assert_eq!(Some(Cow::Borrowed("Copyright Somebody, Somebody Else")), args.copyright);
+        assert_eq!(Some(Cow::Borrowed("https://example.com")), args.project_url);
+        assert_eq!(Some(Cow::Borrowed("native ffi os")), args.tags);
+        assert_eq!(Some(Cow::Borrowed("README.md")), args.readme);
+        assert_eq!(Some(NugetLicense::File("LICENSE.txt".into())), args.license);
+    }
+
+    #[test]
+    fn spec_args_prefer_nuget_metadata() {
+        let cargo = cargo_config(
+            r#"
+                [package]
+                name = "native"
+                version = "0.1.0"
+                authors = ["Somebody"]
+                repository = ""
+                description = ""
+                license = "MIT"
+                license-file = "LICENSE.txt"
+                keywords = ["native", "ffi"]
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.nuget]
+                id = "Native.Runtime"
+                title = "Native Runtime"
+                copyright = "Copyright Nobody"
+                tags = ["runtime"]
+                license = "Apache-2.0"
+            "#,
+        );
+
+        let args = NugetSpecArgs::from(&cargo);
+
+        assert_eq!("Native.Runtime", args.id);
+        assert_eq!(Some(Cow::Borrowed("Native Runtime")), args.title);
+        assert_eq!(Some(Cow::Borrowed("Copyright Nobody")), args.copyright);
+        assert_eq!(Some(Cow::Borrowed("runtime")), args.tags);
+        assert_eq!(Some(NugetLicense::Expression("Apache-2.0".into())), args.license);
+    }
+
+    #[test]
+    fn spec_args_dependencies() {
+        let cargo = cargo_config(
+            r#"
+                [package]
+                name = "native"
+                version = "0.1.0"
+                authors = ["Somebody"]
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.nuget]
+                platforms-dependency = false
+
+                [package.metadata.nuget.dependencies]
+                "Native.Managed" = { version = "[1.0.0, 2.0.0)", target-framework = "net6.0" }
+            "#,
+        );
+
+        let args = NugetSpecArgs::from(&cargo);
+
+        let expected = vec![
+            NugetDependency {
+                id: "Native.Managed".into(),
+                version: "[1.0.0, 2.0.0)".into(),
+                target_framework: Some("net6.0".into()),
+            },
+        ];
+
+        assert_eq!(&expected, &*args.dependencies);
+    }
+
+    #[test]
+    fn spec_args_workspace_dependencies() {
+        let native_a = cargo_config(
+            r#"
+                [package]
+                name = "native_a"
+                version = "0.1.0"
+                authors = ["Somebody"]
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.nuget]
+                platforms-dependency = false
+
+                [dependencies]
+                native_b = { path = "../native_b", version = "0.1" }
+                native_c = { path = "../native_c" }
+                common = { path = "../common" }
+            "#,
+        );
+
+        let mut native_b = cargo_config(
+            r#"
+                [package]
+                name = "native_b"
+                version = "0.1.0"
+                authors = ["Somebody"]
+
+                [lib]
+                crate-type = ["cdylib"]
+
+                [package.metadata.nuget]
+                id = "Native.B"
+            "#,
+        );
+
+        let native_c = cargo_config(
+            r#"
+                [package]
+                name = "native_c"
+                version = "0.2.1"
+                authors = ["Somebody"]
+
+                [lib]
+                crate-type = ["cdylib"]
+            "#,
+        );
+
+        native_b.version = "0.1.0-dev.5".into();
+
+        let packages = [native_b, native_c];
+
+        let args = NugetSpecArgs::from((&native_a, &packages[..]));
+
+        let expected = vec![
+            NugetDependency {
+                id: "Native.B".into(),
+                version: "[0.1.0-dev.5, 0.2.0)".into(),
+                target_framework: None,
+            },
+            NugetDependency {
+                id: "native_c".into(),
+                version: "[0.2.1, )".into(),
+                target_framework: None,
+            },
+        ];
+
+        assert_eq!(&expected, &*args.dependencies);
+    }
+}
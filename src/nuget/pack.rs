@@ -7,7 +7,7 @@ use zip::CompressionMethod;
 use zip::write::{FileOptions, ZipWriter};
 use zip::result::ZipError;
 
-use super::Buf;
+use super::{runtime, Buf};
 use super::util::{openxml, xml};
 use args::Target;
 
@@ -16,8 +16,20 @@ use args::Target;
 pub struct NugetPackArgs<'a> {
     pub id: Cow<'a, str>,
     pub version: Cow<'a, str>,
+    pub lib_name: Cow<'a, str>,
     pub spec: &'a Buf,
     pub cargo_libs: HashMap<Target, Cow<'a, Path>>,
+    /// The version of `rustc` that built each lib, if it's known.
+    pub rustc_versions: HashMap<Target, Cow<'a, str>>,
+    pub files: Vec<NugetFile<'a>>,
+}
+
+/// An additional file to include in a `nupkg`.
+#[derive(Debug, PartialEq)]
+pub struct NugetFile<'a> {
+    pub src: Cow<'a, Path>,
+    /// The folder in the package to put the file in.
+    pub target: Cow<'a, str>,
 }
 
 /// A formatted `nupkg`.
@@ -25,27 +37,25 @@ pub struct NugetPackArgs<'a> {
 pub struct Nupkg<'a> {
     pub name: Cow<'a, str>,
     pub rids: Vec<Cow<'a, str>>,
+    pub uncovered: Vec<Cow<'a, str>>,
     pub buf: Buf,
 }
 
+/// The file in the root of a `nupkg` that lists the `rustc` version each lib was built with.
+const RUSTC_VERSIONS_FILE: &str = "rustc-versions.txt";
+
 fn options() -> FileOptions {
     FileOptions::default().compression_method(CompressionMethod::Deflated)
 }
 
 /// Pack a `nuspec` and native libs into a `nupkg`.
-pub fn pack<'a>(args: NugetPackArgs<'a>) -> Result<Nupkg, NugetPackError> {
+pub fn pack<'a>(args: NugetPackArgs<'a>) -> Result<Nupkg<'a>, NugetPackError> {
     let pkgs: Vec<_> = args.cargo_libs
         .iter()
-        .filter_map(|(target, path)| {
-            if target.is_unknown() {
-                None
-            } else {
-                Some((target.rid(), path))
-            }
-        })
+        .filter_map(|(target, path)| target.rid().map(|rid| (rid, path)))
         .collect();
 
-    if pkgs.len() == 0 {
+    if pkgs.is_empty() {
         Err(NugetPackError::NoValidTargets)?
     }
 
@@ -59,14 +69,19 @@ pub fn pack<'a>(args: NugetPackArgs<'a>) -> Result<Nupkg, NugetPackError> {
         path
     };
 
+    let extensions = args.files
+        .iter()
+        .filter_map(|file| file.src.extension())
+        .filter_map(|extension| extension.to_str());
+
     write_rels(&mut writer, &nuspec_path)?;
-    write_content_types(&mut writer)?;
+    write_content_types(&mut writer, extensions)?;
 
     writer.start_file(nuspec_path.to_string_lossy(), options())?;
-    writer.write_all(&args.spec)?;
+    writer.write_all(args.spec)?;
 
-    for &(ref rid, ref lib_path) in &pkgs {
-        write_lib(&mut writer, &args.id, rid, lib_path).map_err(|e| {
+    for (rid, lib_path) in &pkgs {
+        write_lib(&mut writer, &args.lib_name, rid, lib_path).map_err(|e| {
             NugetPackError::WriteLib {
                 rid: rid.to_string(),
                 lib_path: lib_path.to_string_lossy().into_owned(),
@@ -75,22 +90,41 @@ pub fn pack<'a>(args: NugetPackArgs<'a>) -> Result<Nupkg, NugetPackError> {
         })?;
     }
 
+    write_rustc_versions(&mut writer, &args.rustc_versions)?;
+
+    for file in &args.files {
+        write_file(&mut writer, file).map_err(|e| {
+            NugetPackError::WriteFile {
+                src: file.src.to_string_lossy().into_owned(),
+                err: e,
+            }
+        })?;
+    }
+
     let buf = writer.finish()?.into_inner();
 
-    let rids = pkgs.into_iter().map(|(rid, _)| rid).collect();
+    let rids: Vec<_> = pkgs.into_iter().map(|(rid, _)| rid).collect();
     let name = format!("{}.{}.nupkg", args.id, args.version);
 
+    let uncovered = runtime::uncovered(rids.iter().map(|rid| rid.as_ref()))
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
     Ok(Nupkg {
         name: name.into(),
-        rids: rids,
+        rids,
+        uncovered,
         buf: buf.into(),
     })
 }
 
 /// Write `/runtimes/{rid}/native/{lib}`.
+///
+/// The lib is named after the library target so it can be found by `DllImport`.
 fn write_lib<W>(
     writer: &mut ZipWriter<W>,
-    id: &str,
+    lib_name: &str,
     rid: &str,
     lib_path: &Path,
 ) -> Result<(), NugetWriteLibError>
@@ -101,7 +135,7 @@ where
     path.push("runtimes");
     path.push(rid);
     path.push("native");
-    path.push(id);
+    path.push(lib_name);
 
     if let Some(extension) = lib_path.extension() {
         path.set_extension(extension);
@@ -115,12 +149,70 @@ where
     Ok(())
 }
 
+/// Write `/rustc-versions.txt`, with a `{rid}: {version}` line for each lib.
+///
+/// Nothing is written if none of the libs were built by cargo.
+fn write_rustc_versions<'a, W>(
+    writer: &mut ZipWriter<W>,
+    rustc_versions: &HashMap<Target, Cow<'a, str>>,
+) -> Result<(), NugetPackError>
+where
+    W: Write + Seek,
+{
+    let mut versions: Vec<_> = rustc_versions
+        .iter()
+        .filter_map(|(target, version)| target.rid().map(|rid| (rid, version)))
+        .collect();
+
+    if versions.is_empty() {
+        return Ok(());
+    }
+
+    versions.sort();
+
+    writer.start_file(RUSTC_VERSIONS_FILE, options())?;
+
+    for (rid, version) in versions {
+        info!("'{}' was built with {}", rid, version);
+
+        writeln!(writer, "{}: {}", rid, version)?;
+    }
+
+    Ok(())
+}
+
+/// Write `/{target}/{file}`.
+fn write_file<'a, W>(
+    writer: &mut ZipWriter<W>,
+    file: &NugetFile<'a>,
+) -> Result<(), NugetWriteLibError>
+where
+    W: Write + Seek,
+{
+    let file_name = file.src.file_name().ok_or_else(|| {
+        NugetWriteLibError::BadPath {
+            path: file.src.to_string_lossy().into_owned(),
+        }
+    })?;
+
+    let mut path = PathBuf::new();
+    path.push(file.target.as_ref());
+    path.push(file_name);
+
+    writer.start_file(path.to_string_lossy(), options())?;
+
+    let mut src = File::open(&file.src)?;
+    copy(&mut src, writer)?;
+
+    Ok(())
+}
+
 /// Write `/_rels/.rels`.
 fn write_rels<W>(writer: &mut ZipWriter<W>, nuspec_path: &Path) -> Result<(), NugetPackError>
 where
     W: Write + Seek,
 {
-    let (path, xml) = openxml::relationships(&nuspec_path)?;
+    let (path, xml) = openxml::relationships(nuspec_path)?;
 
     writer.start_file(path.to_string_lossy(), options())?;
     writer.write_all(&xml)?;
@@ -129,11 +221,15 @@ where
 }
 
 /// Write `/[Content_Types].xml`.
-fn write_content_types<W>(writer: &mut ZipWriter<W>) -> Result<(), NugetPackError>
+fn write_content_types<'a, W, I>(
+    writer: &mut ZipWriter<W>,
+    extensions: I,
+) -> Result<(), NugetPackError>
 where
     W: Write + Seek,
+    I: IntoIterator<Item = &'a str>,
 {
-    let (path, xml) = openxml::content_types()?;
+    let (path, xml) = openxml::content_types(extensions)?;
 
     writer.start_file(path.to_string_lossy(), options())?;
     writer.write_all(&xml)?;
@@ -167,6 +263,10 @@ quick_error!{
         WriteLib { rid: String, lib_path: String, err: NugetWriteLibError } {
             display("Error reading lib {} at path {}\nCaused by: {}", rid, lib_path, err)
         }
+        /// An error with an additional file.
+        WriteFile { src: String, err: NugetWriteLibError } {
+            display("Error reading file at path {}\nCaused by: {}", src, err)
+        }
     }
 }
 
@@ -212,8 +312,11 @@ mod tests {
         let args = NugetPackArgs {
             id: "some_pkg".into(),
             version: "0.1.1".into(),
+            lib_name: "some_pkg".into(),
             spec: &vec![].into(),
             cargo_libs: HashMap::new(),
+            rustc_versions: HashMap::new(),
+            files: vec![],
         };
 
         assert_inavlid!(args, NugetPackError::NoValidTargets);
@@ -227,10 +330,54 @@ mod tests {
         let args = NugetPackArgs {
             id: "some_pkg".into(),
             version: "0.1.1".into(),
+            lib_name: "some_pkg".into(),
             spec: &vec![].into(),
             cargo_libs: targets,
+            rustc_versions: HashMap::new(),
+            files: vec![],
         };
 
         assert_inavlid!(args, NugetPackError::NoValidTargets);
     }
+
+    #[test]
+    fn pack_with_rustc_versions() {
+        use std::io::Read;
+        use zip::ZipArchive;
+        use args::{Arch, CrossTarget, Libc};
+
+        let linux = Target::Cross(CrossTarget::Linux(Libc::Gnu, Arch::x64).into());
+        let windows = Target::Cross(CrossTarget::Windows(Arch::x64).into());
+
+        let mut targets = HashMap::new();
+        targets.insert(linux.clone(), PathBuf::from("Cargo.toml").into());
+        targets.insert(windows.clone(), PathBuf::from("Cargo.toml").into());
+
+        let mut rustc_versions = HashMap::new();
+        rustc_versions.insert(linux, "rustc 1.20.0 (f3d6973f4 2017-08-27)".into());
+
+        let spec = vec![].into();
+
+        let args = NugetPackArgs {
+            id: "some_pkg".into(),
+            version: "0.1.1".into(),
+            lib_name: "some_pkg".into(),
+            spec: &spec,
+            cargo_libs: targets,
+            rustc_versions,
+            files: vec![],
+        };
+
+        let nupkg = pack(args).unwrap();
+
+        let mut zip = ZipArchive::new(Cursor::new(nupkg.buf.to_vec())).unwrap();
+
+        let mut versions = String::new();
+        zip.by_name(RUSTC_VERSIONS_FILE)
+            .unwrap()
+            .read_to_string(&mut versions)
+            .unwrap();
+
+        assert_eq!("linux-x64: rustc 1.20.0 (f3d6973f4 2017-08-27)\n", versions);
+    }
 }
@@ -0,0 +1,198 @@
+//! The dotnet runtime graph for resolving native libraries.
+//!
+//! When a package is restored, the consumer's rid is expanded into a list of
+//! compatible rids and the first one that has a `runtimes/{rid}/native` folder
+//! in the package is used. So a `ubuntu.18.04-x64` consumer will fall back to
+//! `ubuntu-x64`, then `debian-x64`, then `linux-x64`.
+//!
+//! This is a version of the graph from `Microsoft.NETCore.Platforms` that's
+//! generated from the known rid operating systems.
+
+use std::collections::VecDeque;
+
+use args::Arch;
+
+/// Rids that consumers of a cross-platform package commonly run on.
+pub const COMMON_RIDS: &[&str] = &[
+    "win-x86",
+    "win-x64",
+    "win-arm64",
+    "linux-x64",
+    "linux-arm64",
+    "linux-musl-x64",
+    "osx-x64",
+    "osx-arm64",
+];
+
+/// Get the rids a consumer on the given rid is compatible with, in order of preference.
+///
+/// The first rid is always the given one.
+pub fn fallbacks(rid: &str) -> Vec<String> {
+    let mut expanded: Vec<String> = Vec::new();
+    let mut queue = VecDeque::new();
+
+    queue.push_back(rid.to_owned());
+
+    while let Some(rid) = queue.pop_front() {
+        if expanded.contains(&rid) {
+            continue;
+        }
+
+        queue.extend(imports(&rid));
+        expanded.push(rid);
+    }
+
+    expanded
+}
+
+/// Get the packaged rid a consumer on the given rid will use.
+///
+/// This will return `None` if none of the packaged rids are compatible with the consumer.
+pub fn best_match<'a, I>(consumer: &str, packaged: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let packaged: Vec<_> = packaged.into_iter().collect();
+
+    fallbacks(consumer)
+        .into_iter()
+        .filter_map(|rid| packaged.iter().find(|packaged| **packaged == rid).copied())
+        .next()
+}
+
+/// Get the common consumer rids that none of the packaged rids are compatible with.
+pub fn uncovered<'a, I>(packaged: I) -> Vec<&'static str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let packaged: Vec<_> = packaged.into_iter().collect();
+
+    COMMON_RIDS
+        .iter()
+        .filter(|rid| best_match(rid, packaged.iter().copied()).is_none())
+        .copied()
+        .collect()
+}
+
+/// Get the rids that a rid directly imports.
+fn imports(rid: &str) -> Vec<String> {
+    let (os, arch, qualifier) = split(rid);
+
+    let (base_os, version) = split_version(os);
+
+    match (arch, qualifier, version) {
+        // `win7-x64-aot` imports `win7-x64`
+        (Some(arch), Some(_), _) => vec![format!("{}-{}", os, arch)],
+        // `ubuntu.18.04-x64` imports `ubuntu.18.04` and `ubuntu-x64`
+        (Some(arch), None, Some(_)) => vec![os.to_owned(), format!("{}-{}", base_os, arch)],
+        // `ubuntu-x64` imports `ubuntu` and `debian-x64`
+        (Some(arch), None, None) => {
+            let mut imports = vec![os.to_owned()];
+
+            match parent(os) {
+                Some("any") | None => (),
+                Some(parent) => imports.push(format!("{}-{}", parent, arch)),
+            }
+
+            imports
+        }
+        // `ubuntu.18.04` imports `ubuntu`
+        (None, _, Some(_)) => vec![base_os.to_owned()],
+        // `ubuntu` imports `debian`
+        (None, _, None) => parent(os).into_iter().map(Into::into).collect(),
+    }
+}
+
+/// Split a rid into its operating system, architecture and qualifier.
+fn split(rid: &str) -> (&str, Option<&str>, Option<&str>) {
+    let mut idx = 0;
+
+    for part in rid.split("-") {
+        if Arch::from_rid(part).is_some() && idx > 0 {
+            let os = &rid[..idx - 1];
+            let arch = &rid[idx..idx + part.len()];
+
+            let qualifier = rid.get(idx + part.len() + 1..);
+
+            return (os, Some(arch), qualifier);
+        }
+
+        idx += part.len() + 1;
+    }
+
+    (rid, None, None)
+}
+
+/// Split a rid operating system from its version, like `ubuntu.18.04` or `win10`.
+fn split_version(os: &str) -> (&str, Option<&str>) {
+    match os.find('.') {
+        Some(idx) => (&os[..idx], Some(&os[idx + 1..])),
+        None if os.starts_with("win") && os.len() > 3 => ("win", Some(&os[3..])),
+        None => (os, None),
+    }
+}
+
+/// Get the operating system a rid operating system inherits from.
+fn parent(os: &str) -> Option<&'static str> {
+    match os {
+        "win" | "unix" => Some("any"),
+        "linux" | "osx" => Some("unix"),
+        "linux-musl" | "centos" | "debian" | "fedora" | "ol" | "opensuse" | "rhel" | "sles" |
+        "tizen" => Some("linux"),
+        "alpine" => Some("linux-musl"),
+        "ubuntu" => Some("debian"),
+        "linuxmint" => Some("ubuntu"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portable_fallbacks() {
+        assert_eq!(vec!["win-x64", "win", "any"], fallbacks("win-x64"));
+        assert_eq!(
+            vec![
+                "linux-musl-arm64",
+                "linux-musl",
+                "linux-arm64",
+                "linux",
+                "unix-arm64",
+                "unix",
+                "any",
+            ],
+            fallbacks("linux-musl-arm64")
+        );
+    }
+
+    #[test]
+    fn versioned_fallbacks() {
+        let fallbacks = fallbacks("ubuntu.22.04-x64");
+
+        assert_eq!("ubuntu.22.04-x64", fallbacks[0]);
+        assert!(fallbacks.contains(&"debian-x64".to_owned()));
+        assert!(fallbacks.contains(&"linux-x64".to_owned()));
+        assert_eq!(Some(&"any".to_owned()), fallbacks.last());
+    }
+
+    #[test]
+    fn best_match_prefers_specific_rids() {
+        let packaged = vec!["linux-x64", "linux-musl-x64", "win-x64"];
+
+        assert_eq!(Some("linux-x64"), best_match("ubuntu.22.04-x64", packaged.clone()));
+        assert_eq!(Some("linux-musl-x64"), best_match("alpine.3.9-x64", packaged.clone()));
+        assert_eq!(Some("win-x64"), best_match("win10-x64-aot", packaged.clone()));
+        assert_eq!(None, best_match("osx-x64", packaged));
+    }
+
+    #[test]
+    fn uncovered_common_rids() {
+        assert_eq!(
+            vec!["win-x86", "win-arm64", "linux-arm64", "osx-x64", "osx-arm64"],
+            uncovered(vec!["win-x64", "linux-x64"])
+        );
+        assert!(uncovered(vec!["any"]).is_empty());
+    }
+}
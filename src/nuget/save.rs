@@ -26,7 +26,7 @@ pub fn save_nupkg<'a>(args: NugetSaveArgs<'a>) -> Result<NupkgPath<'a>, NugetSav
         .create(true)
         .open(&args.path)?;
 
-    f.write_all(&args.nupkg)?;
+    f.write_all(args.nupkg)?;
 
     info!("nupkg written to: {:?}", args.path);
 
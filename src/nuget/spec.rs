@@ -4,7 +4,7 @@ use std::ops::Deref;
 use std::io::Error as IoError;
 use std::borrow::Cow;
 
-use super::Buf;
+use super::{Buf, NugetVersion, NugetVersionError};
 use super::util::xml;
 
 /// Nuget package dependency.
@@ -12,12 +12,30 @@ use super::util::xml;
 pub struct NugetDependency<'a> {
     pub id: Cow<'a, str>,
     pub version: Cow<'a, str>,
+    /// The framework the dependency is needed for, like `netstandard2.0`.
+    pub target_framework: Option<Cow<'a, str>>,
 }
 
 /// A collection of nuget package dependencies.
 #[derive(Debug, PartialEq)]
 pub struct NugetDependencies<'a>(Vec<NugetDependency<'a>>);
 
+impl<'a> NugetDependencies<'a> {
+    /// An empty set of dependencies, without `Microsoft.NETCore.Platforms`.
+    pub fn empty() -> Self {
+        NugetDependencies(vec![])
+    }
+}
+
+impl<'a> Extend<NugetDependency<'a>> for NugetDependencies<'a> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = NugetDependency<'a>>,
+    {
+        self.0.extend(iter)
+    }
+}
+
 /// The default set of dependencies includes `Microsoft.NETCore.Platforms`
 /// which is needed to resolve the right native binary at runtime.
 impl<'a> Default for NugetDependencies<'a> {
@@ -26,6 +44,7 @@ impl<'a> Default for NugetDependencies<'a> {
             NugetDependency {
                 id: "Microsoft.NETCore.Platforms".into(),
                 version: "[1.0.1, )".into(),
+                target_framework: None,
             },
         ])
     }
@@ -39,14 +58,43 @@ impl<'a> Deref for NugetDependencies<'a> {
     }
 }
 
+/// The license for a package.
+#[derive(Debug, PartialEq)]
+pub enum NugetLicense<'a> {
+    /// An SPDX license expression.
+    Expression(Cow<'a, str>),
+    /// The path of a license file within the package.
+    File(Cow<'a, str>),
+}
+
+/// The source repository for a package.
+#[derive(Debug, Default, PartialEq)]
+pub struct NugetRepository<'a> {
+    /// The kind of repository, like `git`.
+    pub kind: Option<Cow<'a, str>>,
+    pub url: Option<Cow<'a, str>>,
+    pub branch: Option<Cow<'a, str>>,
+    pub commit: Option<Cow<'a, str>>,
+}
+
 /// Args for building a `nuspec` metadata file.
 #[derive(Debug, PartialEq)]
 pub struct NugetSpecArgs<'a> {
     pub id: Cow<'a, str>,
     pub version: Cow<'a, str>,
+    pub title: Option<Cow<'a, str>>,
     pub authors: Cow<'a, str>,
-    pub description: Cow<'a, str>,
-    pub repository: Cow<'a, str>,
+    pub owners: Option<Cow<'a, str>>,
+    pub description: Option<Cow<'a, str>>,
+    pub repository: Option<NugetRepository<'a>>,
+    pub project_url: Option<Cow<'a, str>>,
+    pub copyright: Option<Cow<'a, str>>,
+    pub tags: Option<Cow<'a, str>>,
+    /// The path of the icon within the package.
+    pub icon: Option<Cow<'a, str>>,
+    /// The path of the readme within the package.
+    pub readme: Option<Cow<'a, str>>,
+    pub license: Option<NugetLicense<'a>>,
     pub dependencies: NugetDependencies<'a>,
 }
 
@@ -54,12 +102,18 @@ pub struct NugetSpecArgs<'a> {
 #[derive(Debug, PartialEq)]
 pub struct Nuspec<'a> {
     pub id: Cow<'a, str>,
-    pub version: Cow<'a, str>,
+    pub version: NugetVersion,
     pub xml: Buf,
 }
 
 /// Format the input as a `nuspec` xml buffer.
 pub fn spec<'a>(args: NugetSpecArgs<'a>) -> Result<Nuspec<'a>, NugetSpecError> {
+    let version = NugetVersion::parse(&args.version)?;
+
+    if version.is_semver2() {
+        info!("version '{}' needs a NuGet client that supports SemVer 2.0.0", version);
+    }
+
     let mut writer = xml::writer()?;
 
     let pkg_attr = xml::attr(
@@ -69,44 +123,169 @@ pub fn spec<'a>(args: NugetSpecArgs<'a>) -> Result<Nuspec<'a>, NugetSpecError> {
 
     xml::elem(&mut writer, "package", &[pkg_attr], |ref mut writer| {
         xml::elem(writer, "metadata", &[], |ref mut writer| {
-            format_meta(&args, writer)?;
+            format_meta(&args, &version, writer)?;
             format_dependencies(&args.dependencies, writer)
         })
     })?;
 
     Ok(Nuspec {
         id: args.id,
-        version: args.version,
+        version,
         xml: writer.into_inner().into(),
     })
 }
 
 /// Write basic nuspec metadata.
-fn format_meta<'a>(args: &NugetSpecArgs<'a>, writer: &mut xml::Writer) -> Result<(), xml::Error> {
+fn format_meta<'a>(
+    args: &NugetSpecArgs<'a>,
+    version: &NugetVersion,
+    writer: &mut xml::Writer,
+) -> Result<(), xml::Error> {
     xml::val(writer, "id", &args.id)?;
-    xml::val(writer, "version", &args.version)?;
+    xml::val(writer, "version", &version.to_string())?;
+
+    if let Some(ref title) = args.title {
+        xml::val(writer, "title", title)?;
+    }
+
     xml::val(writer, "authors", &args.authors)?;
-    xml::elem(writer, "repository", &[xml::attr("url", &args.repository)], |_| { Ok(()) })?;
-    xml::val(writer, "description", &args.description)
+
+    if let Some(ref owners) = args.owners {
+        xml::val(writer, "owners", owners)?;
+    }
+
+    let license = match args.license {
+        Some(NugetLicense::Expression(ref license)) => Some(("expression", license)),
+        Some(NugetLicense::File(ref license)) => Some(("file", license)),
+        None => None,
+    };
+
+    if let Some((license_type, license)) = license {
+        let type_attr = xml::attr("type", license_type);
+
+        xml::elem(writer, "license", &[type_attr], |ref mut writer| {
+            xml::text(writer, license)
+        })?;
+    }
+
+    if let Some(ref project_url) = args.project_url {
+        xml::val(writer, "projectUrl", project_url)?;
+    }
+
+    if let Some(ref icon) = args.icon {
+        xml::val(writer, "icon", icon)?;
+    }
+
+    if let Some(ref readme) = args.readme {
+        xml::val(writer, "readme", readme)?;
+    }
+
+    if let Some(ref repository) = args.repository {
+        let attrs = [
+            ("type", &repository.kind),
+            ("url", &repository.url),
+            ("branch", &repository.branch),
+            ("commit", &repository.commit),
+        ];
+
+        let attrs: Vec<_> = attrs
+            .iter()
+            .filter_map(|&(name, value)| value.as_ref().map(|value| xml::attr(name, value)))
+            .collect();
+
+        xml::elem(writer, "repository", &attrs, |_| { Ok(()) })?;
+    }
+
+    // NuGet requires a description, so fall back to the title or id
+    let description = args.description
+        .as_ref()
+        .or(args.title.as_ref())
+        .unwrap_or(&args.id);
+
+    xml::val(writer, "description", description)?;
+
+    if let Some(ref copyright) = args.copyright {
+        xml::val(writer, "copyright", copyright)?;
+    }
+
+    if let Some(ref tags) = args.tags {
+        xml::val(writer, "tags", tags)?;
+    }
+
+    Ok(())
 }
 
 /// Write package dependencies.
+///
+/// If any dependency has a target framework then they're all written in groups.
+/// NuGet only uses a group without a `targetFramework` when no other group matches,
+/// so dependencies for any framework are also written in every `targetFramework` group.
 fn format_dependencies<'a>(
     dependencies: &[NugetDependency<'a>],
     writer: &mut xml::Writer,
 ) -> Result<(), xml::Error> {
+    let any: Vec<_> = dependencies
+        .iter()
+        .filter(|dependency| dependency.target_framework.is_none())
+        .collect();
+
+    let mut groups: Vec<(&str, Vec<&NugetDependency>)> = Vec::new();
+
+    for dependency in dependencies {
+        let target_framework = match dependency.target_framework {
+            Some(ref target_framework) => target_framework.as_ref(),
+            None => continue,
+        };
+
+        let group = match groups.iter().position(|&(framework, _)| framework == target_framework) {
+            Some(group) => group,
+            None => {
+                groups.push((target_framework, any.clone()));
+                groups.len() - 1
+            }
+        };
+
+        groups[group].1.push(dependency);
+    }
+
     xml::elem(writer, "dependencies", &[], |ref mut writer| {
-        for dependency in dependencies {
-            let id_attr = xml::attr("id", &dependency.id);
-            let ver_attr = xml::attr("version", &dependency.version);
+        if groups.is_empty() {
+            return format_dependency_list(&any, writer);
+        }
+
+        // The group for any framework is the fallback when no other group matches
+        if !any.is_empty() {
+            xml::elem(writer, "group", &[], |ref mut writer| {
+                format_dependency_list(&any, writer)
+            })?;
+        }
+
+        for &(framework, ref dependencies) in &groups {
+            let attrs = [xml::attr("targetFramework", framework)];
 
-            xml::elem(writer, "dependency", &[id_attr, ver_attr], |_| Ok(()))?;
+            xml::elem(writer, "group", &attrs, |ref mut writer| {
+                format_dependency_list(dependencies, writer)
+            })?;
         }
 
         Ok(())
     })
 }
 
+fn format_dependency_list<'a>(
+    dependencies: &[&NugetDependency<'a>],
+    writer: &mut xml::Writer,
+) -> Result<(), xml::Error> {
+    for dependency in dependencies {
+        let id_attr = xml::attr("id", &dependency.id);
+        let ver_attr = xml::attr("version", &dependency.version);
+
+        xml::elem(writer, "dependency", &[id_attr, ver_attr], |_| Ok(()))?;
+    }
+
+    Ok(())
+}
+
 quick_error!{
     /// An error encountered formatting a Nuspec.
     #[derive(Debug)]
@@ -122,6 +301,12 @@ quick_error!{
             display("Error writing nuget config\nCaused by: {}", err)
             from()
         }
+        /// The package version isn't a valid NuGet version.
+        Version(err: NugetVersionError) {
+            cause(err)
+            display("Error writing nuget config\nCaused by: {}", err)
+            from()
+        }
     }
 }
 
@@ -129,25 +314,47 @@ quick_error!{
 mod tests {
     use super::*;
 
-    #[test]
-    fn format_nuget() {
-        let args = NugetSpecArgs {
+    fn base_args() -> NugetSpecArgs<'static> {
+        NugetSpecArgs {
             id: "native".into(),
             version: "0.1.0".into(),
+            title: None,
             authors: "Someone".into(),
-            description: "A description for this package".into(),
-            repository: "https://github.com/KodrAus/cargo-nuget".into(),
+            owners: None,
+            description: None,
+            repository: None,
+            project_url: None,
+            copyright: None,
+            tags: None,
+            icon: None,
+            readme: None,
+            license: None,
+            dependencies: NugetDependencies(vec![]),
+        }
+    }
+
+    #[test]
+    fn format_nuget() {
+        let args = NugetSpecArgs {
+            description: Some("A description for this package".into()),
+            repository: Some(NugetRepository {
+                url: Some("https://github.com/KodrAus/cargo-nuget".into()),
+                ..Default::default()
+            }),
             dependencies: NugetDependencies(vec![
                 NugetDependency {
                     id: "A".into(),
                     version: "1.0.0".into(),
+                    target_framework: None,
                 },
                 // release notes for RC 11: rewrite from scratch
                 NugetDependency {
                     id: "B".into(),
                     version: "1.0.0-rc11".into(),
+                    target_framework: None,
                 },
             ]),
+            ..base_args()
         };
 
         let nuspec = spec(args).unwrap();
@@ -171,4 +378,248 @@ mod tests {
 
         assert_eq_no_ws!(expected, &nuspec.xml);
     }
+
+    #[test]
+    fn format_nuget_with_metadata() {
+        let args = NugetSpecArgs {
+            id: "Native.Runtime".into(),
+            title: Some("Native Runtime".into()),
+            owners: Some("Someone Else".into()),
+            description: Some("A description for this package".into()),
+            repository: Some(NugetRepository {
+                url: Some("https://github.com/KodrAus/cargo-nuget".into()),
+                ..Default::default()
+            }),
+            project_url: Some("https://example.com".into()),
+            copyright: Some("Copyright Someone".into()),
+            tags: Some("native ffi".into()),
+            icon: Some("icon.png".into()),
+            readme: Some("README.md".into()),
+            license: Some(NugetLicense::Expression("MIT OR Apache-2.0".into())),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>Native.Runtime</id>
+                    <version>0.1.0</version>
+                    <title>Native Runtime</title>
+                    <authors>Someone</authors>
+                    <owners>Someone Else</owners>
+                    <license type="expression">MIT OR Apache-2.0</license>
+                    <projectUrl>https://example.com</projectUrl>
+                    <icon>icon.png</icon>
+                    <readme>README.md</readme>
+                    <repository url="https://github.com/KodrAus/cargo-nuget" />
+                    <description>A description for this package</description>
+                    <copyright>Copyright Someone</copyright>
+                    <tags>native ffi</tags>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
+
+    #[test]
+    fn format_nuget_with_license_file() {
+        let args = NugetSpecArgs {
+            description: Some("A description for this package".into()),
+            repository: Some(NugetRepository {
+                url: Some("https://github.com/KodrAus/cargo-nuget".into()),
+                ..Default::default()
+            }),
+            license: Some(NugetLicense::File("LICENSE.txt".into())),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>0.1.0</version>
+                    <authors>Someone</authors>
+                    <license type="file">LICENSE.txt</license>
+                    <repository url="https://github.com/KodrAus/cargo-nuget" />
+                    <description>A description for this package</description>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
+
+    #[test]
+    fn format_nuget_without_optional_metadata() {
+        let args = base_args();
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>0.1.0</version>
+                    <authors>Someone</authors>
+                    <description>native</description>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
+
+    #[test]
+    fn format_nuget_description_falls_back_to_title() {
+        let args = NugetSpecArgs {
+            title: Some("Native Runtime".into()),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>0.1.0</version>
+                    <title>Native Runtime</title>
+                    <authors>Someone</authors>
+                    <description>Native Runtime</description>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
+
+    #[test]
+    fn format_nuget_with_dependency_groups() {
+        let args = NugetSpecArgs {
+            dependencies: NugetDependencies(vec![
+                NugetDependency {
+                    id: "A".into(),
+                    version: "1.0.0".into(),
+                    target_framework: Some("netstandard2.0".into()),
+                },
+                NugetDependency {
+                    id: "B".into(),
+                    version: "[1.0.0, 2.0.0)".into(),
+                    target_framework: None,
+                },
+                NugetDependency {
+                    id: "C".into(),
+                    version: "1.0.0".into(),
+                    target_framework: Some("netstandard2.0".into()),
+                },
+                NugetDependency {
+                    id: "D".into(),
+                    version: "1.0.0".into(),
+                    target_framework: Some("net6.0".into()),
+                },
+            ]),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        // Dependencies for any framework are in every group, because NuGet only picks one
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>0.1.0</version>
+                    <authors>Someone</authors>
+                    <description>native</description>
+                    <dependencies>
+                        <group>
+                            <dependency id="B" version="[1.0.0, 2.0.0)" />
+                        </group>
+                        <group targetFramework="netstandard2.0">
+                            <dependency id="B" version="[1.0.0, 2.0.0)" />
+                            <dependency id="A" version="1.0.0" />
+                            <dependency id="C" version="1.0.0" />
+                        </group>
+                        <group targetFramework="net6.0">
+                            <dependency id="B" version="[1.0.0, 2.0.0)" />
+                            <dependency id="D" version="1.0.0" />
+                        </group>
+                    </dependencies>
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
+
+    #[test]
+    fn format_nuget_normalizes_version() {
+        let args = NugetSpecArgs {
+            version: "01.0.0.0-Dev.007+8a1c2e3".into(),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>1.0.0-Dev.7+8a1c2e3</version>
+                    <authors>Someone</authors>
+                    <description>native</description>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+        assert_eq!("1.0.0-dev.7", nuspec.version.normalized());
+    }
+
+    #[test]
+    fn format_nuget_with_git_repository() {
+        let args = NugetSpecArgs {
+            repository: Some(NugetRepository {
+                kind: Some("git".into()),
+                url: Some("https://github.com/KodrAus/cargo-nuget".into()),
+                branch: Some("master".into()),
+                commit: Some("8a1c2e3f4b5d6a7c8e9f0a1b2c3d4e5f6a7b8c9d".into()),
+            }),
+            ..base_args()
+        };
+
+        let nuspec = spec(args).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://schemas.microsoft.com/packaging/2012/06/nuspec.xsd">
+                <metadata>
+                    <id>native</id>
+                    <version>0.1.0</version>
+                    <authors>Someone</authors>
+                    <repository type="git" url="https://github.com/KodrAus/cargo-nuget" branch="master" commit="8a1c2e3f4b5d6a7c8e9f0a1b2c3d4e5f6a7b8c9d" />
+                    <description>native</description>
+                    <dependencies />
+                </metadata>
+            </package>
+        "#;
+
+        assert_eq_no_ws!(expected, &nuspec.xml);
+    }
 }
@@ -4,7 +4,17 @@ use std::path::{Path, PathBuf};
 
 use super::xml;
 
-pub fn content_types() -> Result<(PathBuf, Vec<u8>), xml::Error> {
+/// Write the content types for the parts of a package.
+///
+/// Any extensions that aren't already known are given a generic content type.
+pub fn content_types<'a, I>(extensions: I) -> Result<(PathBuf, Vec<u8>), xml::Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut extensions: Vec<_> = extensions.into_iter().collect();
+    extensions.sort();
+    extensions.dedup();
+
     let mut writer = xml::writer()?;
 
     let ns = xml::attr(
@@ -40,6 +50,12 @@ pub fn content_types() -> Result<(PathBuf, Vec<u8>), xml::Error> {
             default(writer, extension, content_type)?;
         }
 
+        for extension in &extensions {
+            if !types.iter().any(|&(known, _)| known == *extension) {
+                default(writer, extension, "application/octet")?;
+            }
+        }
+
         Ok(())
     })?;
 
@@ -104,7 +120,7 @@ mod tests {
 
     #[test]
     fn content_types_file() {
-        let (path, content) = content_types().unwrap();
+        let (path, content) = content_types(vec![]).unwrap();
 
         let expected = br#"
             <?xml version="1.0" encoding="UTF-8"?>
@@ -121,4 +137,25 @@ mod tests {
         assert_eq!(PathBuf::from("[Content_Types].xml"), path);
         assert_eq_no_ws!(expected, &content);
     }
+
+    #[test]
+    fn content_types_file_with_extensions() {
+        let (_, content) = content_types(vec!["png", "dll", "md", "png"]).unwrap();
+
+        let expected = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+                <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml" />
+                <Default Extension="txt" ContentType="application/octet" />
+                <Default Extension="dll" ContentType="application/octet" />
+                <Default Extension="dylib" ContentType="application/octet" />
+                <Default Extension="so" ContentType="application/octet" />
+                <Default Extension="nuspec" ContentType="application/octet" />
+                <Default Extension="md" ContentType="application/octet" />
+                <Default Extension="png" ContentType="application/octet" />
+            </Types>
+        "#;
+
+        assert_eq_no_ws!(expected, &content);
+    }
 }
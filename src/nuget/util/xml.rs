@@ -31,7 +31,7 @@ where
 {
     Attribute {
         name: id.into(),
-        value: value,
+        value,
     }
 }
 
@@ -79,3 +79,13 @@ where
 
     Ok(())
 }
+
+pub fn text<W, V>(writer: &mut EventWriter<W>, value: &V) -> Result<(), Error>
+where
+    W: Write,
+    V: AsRef<str>,
+{
+    writer.write(XmlEvent::Characters(value.as_ref()))?;
+
+    Ok(())
+}
@@ -0,0 +1,536 @@
+//! Convert package versions into the form NuGet expects.
+//!
+//! NuGet versions look like SemVer, but are compared and stored a bit differently:
+//!
+//! - an optional 4th `revision` number is allowed, and only kept when it isn't `0`
+//! - leading zeros in numbers are dropped, so `01.0.0` is `1.0.0`
+//! - the version used to identify a package is lowercase and has no build metadata
+//!
+//! Versions with dotted pre-release labels or build metadata need a client
+//! that supports SemVer 2.0.0, which older NuGet clients don't.
+//!
+//! Cargo version requirements, like `^1.2`, can also be converted into
+//! NuGet version ranges, like `[1.2.0, 2.0.0)`.
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use semver::{Identifier, Version};
+
+/// A normalised NuGet package version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NugetVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub revision: u64,
+    pub pre: Vec<String>,
+    pub build: Vec<String>,
+}
+
+impl NugetVersion {
+    /// Parse a NuGet version, like `1.0.0-beta.1+abc` or `1.0.0.1`.
+    ///
+    /// Versions with just a major and minor number, like `1.0`, are also accepted.
+    pub fn parse(version: &str) -> Result<Self, NugetVersionError> {
+        let invalid = || NugetVersionError::Invalid {
+            version: version.into(),
+        };
+
+        let version_pre = version.trim();
+        let (version_pre, build) = version_pre
+            .split_once('+')
+            .map_or((version_pre, None), |(version_pre, build)| (version_pre, Some(build)));
+        let (numbers, pre) = version_pre
+            .split_once('-')
+            .map_or((version_pre, None), |(numbers, pre)| (numbers, Some(pre)));
+
+        let numbers = numbers
+            .split('.')
+            .map(|number| if number.chars().all(|c| c.is_ascii_digit()) {
+                number.parse().ok()
+            } else {
+                None
+            })
+            .collect::<Option<Vec<u64>>>()
+            .ok_or_else(&invalid)?;
+
+        let labels = |labels: Option<&str>, label: fn(&str) -> Option<String>| {
+            labels
+                .map(|labels| labels.split('.').map(label).collect::<Option<Vec<_>>>())
+                .unwrap_or_else(|| Some(vec![]))
+                .ok_or_else(&invalid)
+        };
+
+        let pre = labels(pre, pre_label)?;
+        let build = labels(build, build_label)?;
+
+        match numbers.len() {
+            2..=4 => Ok(NugetVersion {
+                major: numbers[0],
+                minor: numbers[1],
+                patch: numbers.get(2).cloned().unwrap_or(0),
+                revision: numbers.get(3).cloned().unwrap_or(0),
+                pre,
+                build,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Whether the version needs a client that supports SemVer 2.0.0.
+    ///
+    /// That's any version with a dotted pre-release label or build metadata.
+    pub fn is_semver2(&self) -> bool {
+        self.pre.len() > 1 || !self.build.is_empty()
+    }
+
+    /// Get the version used to identify a package, like `1.0.0-beta`.
+    ///
+    /// This is the version used in file names and feed paths.
+    /// It's lowercase and doesn't include build metadata.
+    pub fn normalized(&self) -> String {
+        let mut version = self.numbers();
+
+        if !self.pre.is_empty() {
+            version.push('-');
+            version.push_str(&self.pre.join("."));
+        }
+
+        version.to_lowercase()
+    }
+
+    fn numbers(&self) -> String {
+        match self.revision {
+            0 => format!("{}.{}.{}", self.major, self.minor, self.patch),
+            revision => format!("{}.{}.{}.{}", self.major, self.minor, self.patch, revision),
+        }
+    }
+}
+
+/// Formats the full version, including any build metadata.
+///
+/// This is the version written to the `nuspec`.
+impl Display for NugetVersion {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}", self.numbers())?;
+
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build.join("."))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Version> for NugetVersion {
+    fn from(version: &'a Version) -> Self {
+        let labels = |labels: &[Identifier]| labels.iter().map(ToString::to_string).collect();
+
+        NugetVersion {
+            major: version.major,
+            minor: version.minor,
+            patch: version.patch,
+            revision: 0,
+            pre: labels(&version.pre),
+            build: labels(&version.build),
+        }
+    }
+}
+
+/// One end of a NuGet version range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NugetVersionBound {
+    pub version: Version,
+    pub inclusive: bool,
+}
+
+/// A NuGet version range, like `[1.0.0, 2.0.0)`.
+///
+/// A missing bound means the range is open at that end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NugetVersionRange {
+    pub min: Option<NugetVersionBound>,
+    pub max: Option<NugetVersionBound>,
+}
+
+impl NugetVersionRange {
+    /// Convert a Cargo version requirement, like `^1.2` or `>=1, <2`, into a NuGet range.
+    ///
+    /// Each comparator in the requirement narrows the range.
+    pub fn from_cargo_req(req: &str) -> Result<Self, NugetVersionError> {
+        let invalid = || NugetVersionError::InvalidRange { req: req.into() };
+
+        let mut range = NugetVersionRange {
+            min: None,
+            max: None,
+        };
+
+        for comparator in req.split(',') {
+            let (min, max) = comparator_bounds(comparator.trim()).ok_or_else(&invalid)?;
+
+            range.min = narrow(range.min, min, Ordering::Greater);
+            range.max = narrow(range.max, max, Ordering::Less);
+        }
+
+        Ok(range)
+    }
+}
+
+impl Display for NugetVersionRange {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) if min == max && min.inclusive => {
+                write!(f, "[{}]", NugetVersion::from(&min.version))
+            }
+            (min, max) => {
+                match *min {
+                    Some(ref min) => {
+                        let open = if min.inclusive { "[" } else { "(" };
+
+                        write!(f, "{}{}, ", open, NugetVersion::from(&min.version))?
+                    }
+                    None => write!(f, "(, ")?,
+                }
+
+                match *max {
+                    Some(ref max) => {
+                        let close = if max.inclusive { "]" } else { ")" };
+
+                        write!(f, "{}{}", NugetVersion::from(&max.version), close)
+                    }
+                    None => write!(f, ")"),
+                }
+            }
+        }
+    }
+}
+
+type Bounds = (Option<NugetVersionBound>, Option<NugetVersionBound>);
+
+/// A version in a requirement, like `1.2`, where missing or wildcard parts are `None`.
+struct PartialVersion {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<Identifier>,
+    wildcard: bool,
+}
+
+impl PartialVersion {
+    fn parse(version: &str) -> Option<Self> {
+        let (numbers, pre) = version
+            .split_once('-')
+            .map_or((version, None), |(numbers, pre)| (numbers, Some(pre)));
+
+        let wildcard = numbers.split('.').any(|part| part == "*" || part == "x" || part == "X");
+
+        let mut parts = numbers.split('.').map(|part| match part {
+            "*" | "x" | "X" => Some(None),
+            part => part.parse().ok().map(Some),
+        });
+
+        let major = parts.next().and_then(|major| major)?;
+        let minor = parts.next().unwrap_or(Some(None))?;
+        let patch = parts.next().unwrap_or(Some(None))?;
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let pre = match pre {
+            Some(pre) => Version::parse(&format!("0.0.0-{}", pre)).ok()?.pre,
+            None => vec![],
+        };
+
+        // Parts after a wildcard, like the `0` in `1.*.0`, are ignored
+        let minor = major.and(minor);
+        let patch = minor.and(patch);
+
+        Some(PartialVersion {
+            major,
+            minor,
+            patch,
+            pre,
+            wildcard,
+        })
+    }
+
+    /// The smallest version that matches, filling missing parts with `0`.
+    fn lowest(&self) -> Version {
+        Version {
+            major: self.major.unwrap_or(0),
+            minor: self.minor.unwrap_or(0),
+            patch: self.patch.unwrap_or(0),
+            pre: self.pre.clone(),
+            build: vec![],
+        }
+    }
+
+    /// The first version after the ones that match, by bumping the last given part.
+    fn next(&self) -> Option<Version> {
+        match (self.major, self.minor, self.patch) {
+            (Some(major), Some(minor), Some(patch)) => Some(version(major, minor, patch + 1)),
+            (Some(major), Some(minor), None) => Some(version(major, minor + 1, 0)),
+            (Some(major), None, _) => Some(version(major + 1, 0, 0)),
+            (None, _, _) => None,
+        }
+    }
+
+    /// The first version that isn't compatible, following Cargo's caret rules.
+    fn next_compatible(&self) -> Option<Version> {
+        match (self.major, self.minor, self.patch) {
+            (Some(0), Some(0), Some(patch)) => Some(version(0, 0, patch + 1)),
+            (Some(0), Some(minor), _) => Some(version(0, minor + 1, 0)),
+            (Some(major), _, _) => Some(version(major + 1, 0, 0)),
+            (None, _, _) => None,
+        }
+    }
+
+    /// The first version with a different minor, following Cargo's tilde rules.
+    fn next_minor(&self) -> Option<Version> {
+        match (self.major, self.minor) {
+            (Some(major), Some(minor)) => Some(version(major, minor + 1, 0)),
+            (Some(major), None) => Some(version(major + 1, 0, 0)),
+            (None, _) => None,
+        }
+    }
+
+    fn is_exact(&self) -> bool {
+        self.patch.is_some()
+    }
+}
+
+/// Get the bounds for a single comparator in a Cargo requirement, like `>=1.2`.
+fn comparator_bounds(comparator: &str) -> Option<Bounds> {
+    let ops = [">=", "<=", ">", "<", "=", "^", "~"];
+
+    let (op, version) = match ops.iter().find(|op| comparator.starts_with(*op)) {
+        Some(op) => (*op, &comparator[op.len()..]),
+        None => ("^", comparator),
+    };
+
+    let version = PartialVersion::parse(version.trim())?;
+
+    // A bare wildcard, like `1.2.*`, matches any version with the given parts
+    let op = match (op, version.wildcard) {
+        ("^", true) => "=",
+        (op, _) => op,
+    };
+
+    let bound = |version: Version, inclusive| {
+        Some(NugetVersionBound {
+            version,
+            inclusive,
+        })
+    };
+
+    let lowest = version.lowest();
+
+    let bounds = match (op, version.is_exact()) {
+        ("^", _) => (bound(lowest, true), version.next_compatible().and_then(|v| bound(v, false))),
+        ("~", _) => (bound(lowest, true), version.next_minor().and_then(|v| bound(v, false))),
+        ("=", true) => (bound(lowest.clone(), true), bound(lowest, true)),
+        ("=", false) => (bound(lowest, true), version.next().and_then(|v| bound(v, false))),
+        (">=", _) => (bound(lowest, true), None),
+        (">", true) => (bound(lowest, false), None),
+        (">", false) => (version.next().and_then(|v| bound(v, true)), None),
+        ("<", _) => (None, bound(lowest, false)),
+        ("<=", true) => (None, bound(lowest, true)),
+        ("<=", false) => (None, version.next().and_then(|v| bound(v, false))),
+        _ => return None,
+    };
+
+    Some(bounds)
+}
+
+fn version(major: u64, minor: u64, patch: u64) -> Version {
+    Version {
+        major,
+        minor,
+        patch,
+        pre: vec![],
+        build: vec![],
+    }
+}
+
+/// Pick the narrower of two bounds.
+///
+/// The `narrower` ordering is the direction a bound moves to narrow the range.
+fn narrow(
+    current: Option<NugetVersionBound>,
+    new: Option<NugetVersionBound>,
+    narrower: Ordering,
+) -> Option<NugetVersionBound> {
+    match (current, new) {
+        (Some(current), Some(new)) => match new.version.cmp(&current.version) {
+            Ordering::Equal if !new.inclusive => Some(new),
+            Ordering::Equal => Some(current),
+            ord if ord == narrower => Some(new),
+            _ => Some(current),
+        },
+        (current, None) => current,
+        (None, new) => new,
+    }
+}
+
+/// Whether a label only contains ASCII letters, digits and `-`.
+fn is_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Check a pre-release label, dropping leading zeros from numeric ones.
+fn pre_label(label: &str) -> Option<String> {
+    match (is_label(label), label.chars().all(|c| c.is_ascii_digit())) {
+        (false, _) => None,
+        (true, true) => label.parse::<u64>().ok().map(|label| label.to_string()),
+        (true, false) => Some(label.into()),
+    }
+}
+
+/// Check a build metadata label, which is kept as-is.
+///
+/// Build metadata isn't compared, so a numeric label like a commit hash keeps its leading zeros.
+fn build_label(label: &str) -> Option<String> {
+    if is_label(label) {
+        Some(label.into())
+    } else {
+        None
+    }
+}
+
+quick_error!{
+    /// An error encountered reading a NuGet version.
+    #[derive(Debug)]
+    pub enum NugetVersionError {
+        /// The version isn't a valid NuGet version.
+        Invalid { version: String } {
+            display("'{}' is not a valid NuGet version", version)
+        }
+        /// The requirement can't be converted into a NuGet version range.
+        InvalidRange { req: String } {
+            display("'{}' can't be converted into a NuGet version range", req)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(version: &str) -> String {
+        NugetVersion::parse(version).unwrap().normalized()
+    }
+
+    #[test]
+    fn normalize_versions() {
+        let cases = vec![
+            ("1.0.0", "1.0.0"),
+            ("1.0", "1.0.0"),
+            ("01.002.0003", "1.2.3"),
+            ("1.0.0.0", "1.0.0"),
+            ("1.0.0.4", "1.0.0.4"),
+            ("1.0.0-Beta", "1.0.0-beta"),
+            ("1.0.0-dev.007", "1.0.0-dev.7"),
+            ("1.0.0-rc.1+Build.5", "1.0.0-rc.1"),
+        ];
+
+        for (version, expected) in cases {
+            assert_eq!(expected, normalized(version), "{}", version);
+        }
+    }
+
+    #[test]
+    fn full_version_keeps_build_metadata() {
+        let version = NugetVersion::parse("1.0.0-Beta.01+8a1c2e3").unwrap();
+
+        assert_eq!("1.0.0-Beta.1+8a1c2e3", version.to_string());
+    }
+
+    #[test]
+    fn full_version_keeps_numeric_build_metadata() {
+        let version = NugetVersion::parse("1.0.0-dev.007+0123456").unwrap();
+
+        assert_eq!("1.0.0-dev.7+0123456", version.to_string());
+    }
+
+    #[test]
+    fn invalid_versions() {
+        let cases = vec!["", "1", "1.0.0.0.0", "1.a.0", "1.0.0-", "1.0.0-beta..1", "1.0.0+a_b"];
+        let cases = cases.into_iter().chain(vec!["1.0.0-bêta", "1.0.0+ä1c2e3"]);
+
+        for version in cases {
+            assert!(NugetVersion::parse(version).is_err(), "{}", version);
+        }
+    }
+
+    #[test]
+    fn semver2_versions() {
+        let cases = vec![
+            ("1.0.0", false),
+            ("1.0.0-beta1", false),
+            ("1.0.0-dev.1500000000", true),
+            ("1.0.0+8a1c2e3", true),
+        ];
+
+        for (version, expected) in cases {
+            let version = NugetVersion::parse(version).unwrap();
+
+            assert_eq!(expected, version.is_semver2(), "{}", version);
+        }
+    }
+
+    #[test]
+    fn from_semver() {
+        let version = Version::parse("1.2.3-dev.42+8a1c2e3").unwrap();
+
+        let version = NugetVersion::from(&version);
+
+        assert_eq!("1.2.3-dev.42", version.normalized());
+        assert_eq!("1.2.3-dev.42+8a1c2e3", version.to_string());
+        assert!(version.is_semver2());
+    }
+
+    #[test]
+    fn ranges_from_cargo_reqs() {
+        let cases = vec![
+            ("1.2.3", "[1.2.3, 2.0.0)"),
+            ("^1.2", "[1.2.0, 2.0.0)"),
+            ("^0.2.3", "[0.2.3, 0.3.0)"),
+            ("^0.0.3", "[0.0.3, 0.0.4)"),
+            ("^0.0", "[0.0.0, 0.1.0)"),
+            ("~1.2.3", "[1.2.3, 1.3.0)"),
+            ("~1", "[1.0.0, 2.0.0)"),
+            ("=1.2.3", "[1.2.3]"),
+            ("=1.2", "[1.2.0, 1.3.0)"),
+            ("1.*", "[1.0.0, 2.0.0)"),
+            ("1.2.*", "[1.2.0, 1.3.0)"),
+            ("*", "[0.0.0, )"),
+            (">=1, <2", "[1.0.0, 2.0.0)"),
+            (">1.2.3", "(1.2.3, )"),
+            (">1.2", "[1.3.0, )"),
+            ("<=2", "(, 3.0.0)"),
+            ("<=2.0.0", "(, 2.0.0]"),
+            (">=1.0.0, <1.5.0, >=1.2.0", "[1.2.0, 1.5.0)"),
+            ("^1.0.0-beta.1", "[1.0.0-beta.1, 2.0.0)"),
+        ];
+
+        for (req, expected) in cases {
+            let range = NugetVersionRange::from_cargo_req(req).unwrap();
+
+            assert_eq!(expected, range.to_string(), "{}", req);
+        }
+    }
+
+    #[test]
+    fn invalid_cargo_reqs() {
+        let cases = vec!["", "a.b", "1.2.3.4", "!1.0", ">=1, "];
+
+        for req in cases {
+            assert!(NugetVersionRange::from_cargo_req(req).is_err(), "{}", req);
+        }
+    }
+}
@@ -1,16 +1,25 @@
 use std::error::Error;
+use std::iter;
 use clap::ArgMatches;
 
 use {cargo, nuget};
+use cargo::CargoVersionStrategy;
+use args::WORKSPACE_ARG;
 
-pub fn call(args: &ArgMatches) -> Result<(), Box<Error>> {
-    let mut cargo_toml = pass!("reading cargo manifest" => args => cargo::parse_toml);
+pub fn call(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if args.is_present(WORKSPACE_ARG) {
+        return call_workspace(args);
+    }
 
-    let local = pass!("adding local version tag" => &cargo_toml => cargo::local_version_tag);
+    let cargo_meta = pass!("reading cargo metadata" => args => cargo::metadata);
+
+    let mut cargo_toml = pass!("reading cargo manifest" => &cargo_meta => cargo::parse_toml);
+
+    let local = pass!("generating package version" => (args, &cargo_meta, &cargo_toml, CargoVersionStrategy::Timestamp) => cargo::local_version_tag);
 
     cargo_toml.version = local.version;
 
-    let cargo_libs = pass!("building Rust lib" => (args, &cargo_toml) => |args| {
+    let cargo_libs = pass!("building Rust lib" => (args, &cargo_meta, &cargo_toml) => |args| {
         let result = cargo::build_local(args);
 
         result.map(|result| vec![result])
@@ -18,9 +27,33 @@ pub fn call(args: &ArgMatches) -> Result<(), Box<Error>> {
 
     let nuspec = pass!("building nuspec" => &cargo_toml => nuget::spec);
 
-    let nupkg = pass!("building nupkg" => (&nuspec, &cargo_libs) => nuget::pack);
+    let nupkg = pass!("building nupkg" => (&cargo_toml, &nuspec, &cargo_libs) => nuget::pack);
 
     pass!("saving nupkg" => (args, &nupkg) => nuget::save_nupkg);
 
     Ok(())
 }
+
+fn call_workspace(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let cargo_meta = pass!("reading cargo workspace metadata" => args => cargo::workspace_metadata);
+
+    let mut cargo_tomls = pass!("reading cargo manifests" => &cargo_meta => cargo::parse_workspace_toml);
+
+    for cargo_toml in &mut cargo_tomls {
+        let local = pass!("generating package version" => (args, &cargo_meta, &*cargo_toml, CargoVersionStrategy::Timestamp) => cargo::local_version_tag);
+
+        cargo_toml.version = local.version;
+    }
+
+    let cargo_libs = pass!("building Rust libs" => (args, &cargo_meta, &cargo_tomls[..]) => cargo::build_workspace);
+
+    for (cargo_toml, cargo_lib) in cargo_tomls.iter().zip(&cargo_libs) {
+        let nuspec = pass!("building nuspec" => (cargo_toml, &cargo_tomls[..]) => nuget::spec);
+
+        let nupkg = pass!("building nupkg" => (cargo_toml, &nuspec, iter::once(cargo_lib)) => nuget::pack);
+
+        pass!("saving nupkg" => (args, &nupkg) => nuget::save_nupkg);
+    }
+
+    Ok(())
+}
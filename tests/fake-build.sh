@@ -0,0 +1,12 @@
+#!/bin/sh
+# Stands in for a cross build command like `cargo zigbuild` in tests.
+#
+# Instead of building anything, it writes the args, rustflags and linker it was run with
+# to `$FAKE_BUILD_OUTPUT` and reports that file as the library it built.
+set -e
+
+echo "args: $*" > "$FAKE_BUILD_OUTPUT"
+echo "rustflags: $CARGO_ENCODED_RUSTFLAGS" >> "$FAKE_BUILD_OUTPUT"
+echo "linker: $FAKE_BUILD_LINKER" >> "$FAKE_BUILD_OUTPUT"
+
+echo "{\"reason\":\"compiler-artifact\",\"target\":{\"name\":\"native_test\",\"kind\":[\"cdylib\"]},\"filenames\":[\"$FAKE_BUILD_OUTPUT\"]}"
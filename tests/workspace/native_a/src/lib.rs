@@ -0,0 +1,4 @@
+#[no_mangle]
+pub extern fn run() -> bool {
+    true
+}